@@ -0,0 +1,47 @@
+use std::{
+    io::{Seek, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use flate2::{Compression, write::GzEncoder};
+use meshes::conf::RenderedFile;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+const fn mode(file: &RenderedFile) -> u32 {
+    if file.private { 0o600 } else { 0o644 }
+}
+
+/// Writes `files` as a gzipped tarball, under the `root` directory.
+pub fn write_tar_gz(w: impl Write, root: &str, files: &[RenderedFile]) -> Result<()> {
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let encoder = GzEncoder::new(w, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for file in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(file.contents.len() as u64);
+        header.set_mode(mode(file));
+        header.set_mtime(mtime);
+        builder.append_data(
+            &mut header,
+            format!("{}/{}", root, file.name),
+            file.contents.as_bytes(),
+        )?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Writes `files` as a zip archive, under the `root` directory.
+pub fn write_zip(w: impl Write + Seek, root: &str, files: &[RenderedFile]) -> Result<()> {
+    let mut zip = ZipWriter::new(w);
+    for file in files {
+        zip.start_file(
+            format!("{}/{}", root, file.name),
+            SimpleFileOptions::default().unix_permissions(mode(file)),
+        )?;
+        zip.write_all(file.contents.as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
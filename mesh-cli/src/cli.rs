@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand, value_parser};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum, value_parser};
+use meshes::conf::DEFAULT_INTERFACE;
 
 #[derive(Parser, Debug)]
 #[clap(subcommand_required = true, arg_required_else_help = true)]
@@ -9,6 +10,9 @@ pub struct Cli {
     /// Config file path
     #[arg(short, long)]
     pub config: Box<str>,
+    /// Config file format [default: from the config file extension, else json]
+    #[arg(long, value_name = "CONFIG_FORMAT", value_enum)]
+    pub config_format: Option<ConfigFormat>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -25,8 +29,79 @@ pub enum Commands {
         /// Output directory
         #[arg(short, long)]
         output: Box<str>,
+        /// Output formats, separated by commas [default: wg-quick, unless `--template` is given]
+        #[arg(short, long, value_enum, value_delimiter = ',')]
+        format: Vec<Format>,
+        /// Also render every mesh through a minijinja template file
+        #[arg(short, long)]
+        template: Option<Box<str>>,
+        /// Bundle each mesh's files into one archive
+        #[arg(short, long, value_enum)]
+        archive: Option<Archive>,
+        /// WireGuard interface name, unless the config defines `networks`
+        #[arg(long, default_value = DEFAULT_INTERFACE)]
+        interface: Box<str>,
+    },
+
+    #[command(about = "Export the mesh config for a deployment tool")]
+    Export {
+        /// Output directory
+        #[arg(short, long)]
+        output: Box<str>,
+        /// Export format
+        #[arg(short, long, value_enum)]
+        format: ExportFormat,
+        /// WireGuard interface name, each network's own name with `networks`
+        #[arg(long, default_value = DEFAULT_INTERFACE)]
+        interface: Box<str>,
+        /// Kubernetes namespace of the generated manifests
+        #[arg(long)]
+        namespace: Option<Box<str>>,
+        /// Prefix of the generated Kubernetes object names
+        #[arg(long, default_value = "")]
+        name_prefix: Box<str>,
+    },
+
+    #[command(about = "Import existing configs into a mesh config file")]
+    #[command(group(ArgGroup::new("source").required(true)))]
+    Import {
+        /// Directory of wg-quick `.conf` files, named after their tags
+        #[arg(long, group = "source")]
+        from_wgquick: Option<Box<str>>,
+        /// wg-meshconf `database.csv` file
+        #[arg(long, group = "source")]
+        from_wgmeshconf: Option<Box<str>>,
     },
 
+    #[command(about = "Show a mesh's wg-quick config as a QR code")]
+    Qr {
+        /// The tag of the `mesh` to show
+        #[arg(short, long)]
+        tag: Box<str>,
+        /// QR code format
+        #[arg(short, long, value_enum, default_value_t = QrFormat::Utf8)]
+        format: QrFormat,
+        /// Output directory, required for image formats
+        #[arg(short, long, required_if_eq_any = [("format", "png"), ("format", "svg")])]
+        output: Option<Box<str>>,
+    },
+
+    #[command(about = "Upgrade the config file to the latest schema version")]
+    Migrate,
+
+    #[command(about = "Print which meshes peer with each other")]
+    Topology,
+
+    #[command(about = "Print the routes of a `mesh` and the peer each goes through")]
+    Routes {
+        /// The tag of the `mesh` to show
+        #[arg(short, long)]
+        tag: Box<str>,
+    },
+
+    #[command(about = "Print the JSON Schema of the config file")]
+    Schema,
+
     #[command(about = "Append a `mesh` to the config")]
     Append {
         /// The tag of appending `mesh`
@@ -40,3 +115,57 @@ pub enum Commands {
         in_place: bool,
     },
 }
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// JSON
+    Json,
+    /// YAML
+    Yaml,
+    /// TOML
+    Toml,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// wg-quick(8) configuration
+    WgQuick,
+    /// `wg setconf` configuration with an addressing script
+    Wg,
+    /// systemd-networkd .netdev and .network units
+    Networkd,
+    /// NetworkManager keyfile
+    NetworkManager,
+    /// OpenWrt UCI network config and `uci batch` script
+    Uci,
+    /// MikroTik RouterOS script
+    RouterOs,
+    /// NixOS module
+    Nix,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Ansible inventory, host_vars and a vault file for private keys
+    Ansible,
+    /// Kubernetes Secret manifests holding wg-quick configs
+    K8s,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Archive {
+    /// gzipped tarball
+    TarGz,
+    /// zip archive
+    Zip,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrFormat {
+    /// UTF-8 block characters printed to the terminal
+    Utf8,
+    /// PNG image
+    Png,
+    /// SVG image
+    Svg,
+}
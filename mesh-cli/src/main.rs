@@ -1,10 +1,12 @@
+mod archive;
 mod cli;
+mod qr;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs,
     hash::Hash,
-    io,
+    io::{self, Write as _},
     net::{Ipv4Addr, Ipv6Addr},
     ops::{Add, BitAnd, Not, Shl, Sub},
     path::Path,
@@ -15,19 +17,160 @@ use anyhow::{Result, bail};
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 use cidr::{Ipv4Cidr, Ipv6Cidr};
 use clap::{CommandFactory as _, FromArgMatches as _};
-use cli::{Cli, Commands};
+use cli::{Archive, Cli, Commands, ConfigFormat, ExportFormat, Format, QrFormat};
 use meshes::{
-    conf::Conf,
-    mesh::{FromJson as _, Mesh, Meshs, ToJson as _},
+    conf::{
+        Conf, NetworkManager, Networkd, Nix, Readme, RenderedFile, Renderer, RouterOs, Template,
+        Uci, WgQuick, WgSetconf,
+    },
+    export::{Ansible, Exporter, Kubernetes},
+    import,
+    mesh::{
+        FromJson as _, FromToml as _, FromYaml as _, Mesh, Meshs, ToJson as _, ToToml as _,
+        ToYaml as _, VERSION,
+    },
+    migrate::{self, Migrated},
 };
+use qrcode::QrCode;
 use x25519_dalek::{PublicKey, StaticSecret};
 
+const WARN: &str = "\x1b[0;33mWARNING\x1b[0m";
 const IPV4_NETWORK_BROADCAST_OVERHEAD: u32 = 2;
 const RESERVED_IPV6_ADDRESS_COUNT: u32 = 1;
 
-fn read_config(path: impl AsRef<Path>) -> Result<Meshs> {
+/// Picks the config format from its file extension, falling back to JSON.
+fn config_format(path: impl AsRef<Path>) -> ConfigFormat {
+    match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => ConfigFormat::Yaml,
+        Some("toml") => ConfigFormat::Toml,
+        _ => ConfigFormat::Json,
+    }
+}
+
+/// Loads the config, upgrading it in memory if it predates [`VERSION`].
+fn load_config(path: impl AsRef<Path>, format: ConfigFormat) -> Result<(Meshs, Migrated)> {
     let buf = fs::read_to_string(path.as_ref())?;
-    Ok(Meshs::from_json(buf)?)
+    let value: serde_json::Value = match format {
+        ConfigFormat::Json => serde_json::from_str(&buf)?,
+        ConfigFormat::Yaml => serde_norway::from_str(&buf)?,
+        ConfigFormat::Toml => toml::from_str(&buf)?,
+    };
+    let mut migrated = migrate::migrate(value)?;
+    let meshs = if migrated.changes.is_empty() {
+        // Parse the text again so errors point at a line
+        match format {
+            ConfigFormat::Json => Meshs::from_json(buf)?,
+            ConfigFormat::Yaml => Meshs::from_yaml(buf)?,
+            ConfigFormat::Toml => Meshs::from_toml(buf)?,
+        }
+    } else {
+        serde_json::from_value(migrated.value.take())?
+    };
+    Ok((meshs, migrated))
+}
+
+fn read_config(path: impl AsRef<Path>, format: ConfigFormat) -> Result<Meshs> {
+    let (meshs, migrated) = load_config(path, format)?;
+    if !migrated.changes.is_empty() {
+        eprintln!(
+            "{}: config is at version {}, run `mesh migrate` to upgrade it to {}",
+            WARN, migrated.from, VERSION
+        );
+    }
+    Ok(meshs)
+}
+
+fn serialize_config(meshs: &Meshs, format: ConfigFormat) -> Result<Box<str>> {
+    Ok(match format {
+        ConfigFormat::Json => meshs.to_json()?,
+        ConfigFormat::Yaml => meshs.to_yaml()?,
+        ConfigFormat::Toml => meshs.to_toml()?,
+    })
+}
+
+fn create_file(path: &Path, private: bool) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _};
+        let mode = if private { 0o600 } else { 0o644 };
+        // The mode only applies to new files, existing ones keep theirs
+        let f = options.mode(mode).open(path)?;
+        f.set_permissions(fs::Permissions::from_mode(mode))?;
+        Ok(f)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = private;
+        Ok(options.open(path)?)
+    }
+}
+
+fn write_file(path: &Path, contents: &[u8], private: bool) -> Result<()> {
+    create_file(path, private)?.write_all(contents)?;
+    Ok(())
+}
+
+fn write_rendered(dir: &Path, file: &RenderedFile) -> Result<()> {
+    let path = dir.join(file.name.as_ref());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_file(&path, file.contents.as_bytes(), file.private)
+}
+
+/// Loads a template file; output files take its extension, after stripping
+/// a trailing template extension such as `.j2`, or else `.txt`.
+fn read_template(path: impl AsRef<Path>) -> Result<Template> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = ["j2", "jinja", "jinja2"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext)?.strip_suffix('.'))
+        .unwrap_or(&name);
+    let extension = Path::new(stem)
+        .extension()
+        .unwrap_or("txt".as_ref())
+        .to_string_lossy();
+    Ok(Template::new(name.as_ref(), source, extension.as_ref())?)
+}
+
+/// The meshes `convert` renders: each of the `networks` on its interface, or
+/// else all of `meshs`.
+fn meshes(conf: Conf) -> Result<Box<[Conf]>> {
+    if conf.meshs.networks.is_empty() {
+        Ok(Box::new([conf]))
+    } else {
+        Ok(conf.networks()?)
+    }
+}
+
+fn confirm_overwrite(path: &Path) -> Result<()> {
+    if path.exists() {
+        eprintln!("Config file already exsits");
+        eprint!("continue? [y/N]");
+        let mut input = String::with_capacity(2);
+        io::stdin().read_line(&mut input).unwrap();
+        let input = input.trim();
+        if input.len() > 1 {
+            bail!("Invalid input")
+        }
+        if !input.to_ascii_lowercase().contains('y') {
+            bail!("Aborted")
+        }
+    }
+    Ok(())
+}
+
+fn check_output_dir(output: &Path) -> Result<()> {
+    if output.is_file() {
+        bail!("Output should not be file")
+    } else if !output.exists() {
+        bail!("Output directory does not exist")
+    }
+    Ok(())
 }
 
 trait TruncateToUsize {
@@ -109,22 +252,13 @@ fn main() -> Result<()> {
     let mut cmd = Cli::command();
     cmd.build();
     let args = Cli::from_arg_matches(&cmd.clone().get_matches())?;
+    let config_format = args
+        .config_format
+        .unwrap_or_else(|| config_format(args.config.as_ref()));
     match args.command {
         Commands::Init { count } => {
             let path = Path::new(args.config.as_ref());
-            if path.exists() {
-                eprintln!("Config file already exsits");
-                eprint!("continue? [y/N]");
-                let mut input = String::with_capacity(2);
-                io::stdin().read_line(&mut input).unwrap();
-                let input = input.trim();
-                if input.len() > 1 {
-                    bail!("Invalid input")
-                }
-                if !input.to_ascii_lowercase().contains('y') {
-                    bail!("Aborted")
-                }
-            }
+            confirm_overwrite(path)?;
             if let Some(count) = count {
                 let ipv4_prefix = 32
                     - ((count + IPV4_NETWORK_BROADCAST_OVERHEAD) as f32)
@@ -154,30 +288,69 @@ fn main() -> Result<()> {
                 }
                 fs::write(
                     path,
-                    Meshs::new(meshs, ipv4_prefix, ipv6_prefix)
-                        .to_json()?
+                    serialize_config(&Meshs::new(meshs, ipv4_prefix, ipv6_prefix), config_format)?
                         .as_bytes(),
                 )?;
             } else {
                 fs::write(
                     path,
-                    Meshs::new([Mesh::default()], 24, 120).to_json()?.as_bytes(),
+                    serialize_config(&Meshs::new([Mesh::default()], 24, 120), config_format)?
+                        .as_bytes(),
                 )?;
             }
         }
-        Commands::Convert { output } => {
+        Commands::Convert {
+            output,
+            mut format,
+            template,
+            archive,
+            interface,
+        } => {
             let output = Path::new(output.as_ref());
-            if output.is_file() {
-                bail!("Output should not be file")
-            } else if !output.exists() {
-                bail!("Output directory does not exist")
+            check_output_dir(output)?;
+            if format.is_empty() && template.is_none() {
+                format.push(Format::WgQuick);
+            }
+            let mut renderers: Vec<Box<dyn Renderer>> = Vec::with_capacity(format.len() + 2);
+            for (i, f) in format.iter().enumerate() {
+                if format[..i].contains(f) {
+                    continue;
+                }
+                renderers.push(match f {
+                    Format::WgQuick => Box::new(WgQuick),
+                    Format::Wg => Box::new(WgSetconf),
+                    Format::Networkd => Box::new(Networkd::default()),
+                    Format::NetworkManager => Box::new(NetworkManager),
+                    Format::Uci => Box::new(Uci),
+                    Format::RouterOs => Box::new(RouterOs),
+                    Format::Nix => Box::new(Nix::default()),
+                });
+            }
+            if let Some(template) = template {
+                renderers.push(Box::new(read_template(template.as_ref())?));
+            }
+            if archive.is_some() {
+                renderers.push(Box::new(Readme));
+            }
+            let mut conf = Conf::new(read_config(args.config.as_ref(), config_format)?);
+            conf.interface = interface;
+            let mut config_map: HashMap<Box<str>, Vec<RenderedFile>> = HashMap::new();
+            for renderer in renderers.iter() {
+                for (tag, files) in conf.create_all(renderer.as_ref())? {
+                    config_map.entry(tag).or_default().extend(files);
+                }
+            }
+            for files in config_map.values() {
+                for (i, file) in files.iter().enumerate() {
+                    if files[..i].iter().any(|other| other.name == file.name) {
+                        bail!("Several output formats write `{}`", file.name)
+                    }
+                }
             }
-            let config_map = Conf::new(read_config(args.config.as_ref())?).create_all()?;
             let mut tag_warned = false;
-            for (tag, config) in config_map {
+            for (tag, files) in config_map {
                 if tag.is_empty() {
                     if !tag_warned {
-                        const WARN: &str = "\x1b[0;33mWARNING\x1b[0m";
                         eprintln!(
                             "{}: One or more of the meshes has a empty tag, it will be ignored",
                             WARN
@@ -186,17 +359,214 @@ fn main() -> Result<()> {
                     }
                     continue;
                 }
-                let path = output.join(format!("{}.conf", tag));
-                fs::write(path, config.as_bytes())?;
+                let private = files.iter().any(|file| file.private);
+                match archive {
+                    Some(Archive::TarGz) => archive::write_tar_gz(
+                        create_file(&output.join(format!("{}.tar.gz", tag)), private)?,
+                        &tag,
+                        &files,
+                    )?,
+                    Some(Archive::Zip) => archive::write_zip(
+                        create_file(&output.join(format!("{}.zip", tag)), private)?,
+                        &tag,
+                        &files,
+                    )?,
+                    None => {
+                        for file in files.iter() {
+                            write_rendered(output, file)?;
+                        }
+                    }
+                }
             }
         }
+        Commands::Export {
+            output,
+            format,
+            interface,
+            namespace,
+            name_prefix,
+        } => {
+            let output = Path::new(output.as_ref());
+            check_output_dir(output)?;
+            let exporter: Box<dyn Exporter> = match format {
+                ExportFormat::Ansible => Box::new(Ansible),
+                ExportFormat::K8s => Box::new(Kubernetes {
+                    namespace,
+                    name_prefix,
+                }),
+            };
+            let mut conf = Conf::new(read_config(args.config.as_ref(), config_format)?);
+            if conf.meshs.networks.is_empty() {
+                conf.interface = interface;
+                for file in exporter.export(&conf)?.iter() {
+                    write_rendered(output, file)?;
+                }
+            } else {
+                conf.validate()?;
+                for network in conf.networks()?.iter() {
+                    let output = output.join(network.interface.as_ref());
+                    for file in exporter.export(network)?.iter() {
+                        write_rendered(&output, file)?;
+                    }
+                }
+            }
+        }
+        Commands::Import {
+            from_wgquick,
+            from_wgmeshconf,
+        } => {
+            let path = Path::new(args.config.as_ref());
+            confirm_overwrite(path)?;
+            let imported = if let Some(csv) = from_wgmeshconf {
+                import::wg_meshconf(fs::File::open(csv.as_ref())?)?
+            } else {
+                let mut configs = Vec::new();
+                for entry in fs::read_dir(from_wgquick.unwrap().as_ref())? {
+                    let entry_path = entry?.path();
+                    if entry_path.extension().is_none_or(|ext| ext != "conf") {
+                        continue;
+                    }
+                    let tag = entry_path
+                        .file_stem()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned();
+                    configs.push((tag, fs::read_to_string(&entry_path)?));
+                }
+                configs.sort();
+                import::wg_quick(configs.iter().map(|(tag, config)| (&**tag, &**config)))?
+            };
+            for warning in imported.warnings.iter() {
+                eprintln!("{}: {}", WARN, warning);
+            }
+            fs::write(
+                path,
+                serialize_config(&imported.meshs, config_format)?.as_bytes(),
+            )?;
+        }
+        Commands::Qr {
+            tag,
+            format,
+            output,
+        } => {
+            let conf = Conf::new(read_config(args.config.as_ref(), config_format)?);
+            let labelled = !conf.meshs.networks.is_empty();
+            let mut found = false;
+            for conf in meshes(conf)?.iter() {
+                let Some(mesh) = conf.meshs.iter().find(|mesh| mesh.tag == tag) else {
+                    continue;
+                };
+                found = true;
+                let files = conf.create_single(&WgQuick, mesh)?;
+                let code = QrCode::new(files[0].contents.as_bytes())?;
+                let (extension, contents) = match format {
+                    QrFormat::Utf8 => {
+                        if labelled {
+                            println!("[{}]", conf.interface);
+                        }
+                        println!("{}", qr::to_utf8(&code));
+                        continue;
+                    }
+                    QrFormat::Png => ("png", qr::to_png(&code)?),
+                    QrFormat::Svg => ("svg", qr::to_svg(&code).into_bytes()),
+                };
+                let mut output = Path::new(output.as_deref().unwrap()).to_path_buf();
+                check_output_dir(&output)?;
+                if labelled {
+                    output.push(conf.interface.as_ref());
+                    fs::create_dir_all(&output)?;
+                }
+                write_file(
+                    &output.join(format!("{}.{}", tag, extension)),
+                    &contents,
+                    true,
+                )?;
+            }
+            if !found {
+                bail!("No mesh tagged `{}`", tag)
+            }
+        }
+        Commands::Migrate => {
+            let (meshs, migrated) = load_config(args.config.as_ref(), config_format)?;
+            if migrated.changes.is_empty() {
+                println!("Config is already at version {}", VERSION);
+                return Ok(());
+            }
+            fs::write(
+                args.config.as_ref(),
+                serialize_config(&meshs, config_format)?.as_bytes(),
+            )?;
+            for change in migrated.changes.iter() {
+                println!("{}", change);
+            }
+        }
+        Commands::Topology => {
+            let conf = Conf::new(read_config(args.config.as_ref(), config_format)?);
+            conf.validate()?;
+            let labelled = !conf.meshs.networks.is_empty();
+            for conf in meshes(conf)?.iter() {
+                if labelled {
+                    println!("[{}]", conf.interface);
+                }
+                for mesh in conf.meshs.iter() {
+                    let node = conf.node(mesh);
+                    let peers: Box<[_]> = node.peers.iter().map(|peer| &*peer.mesh.tag).collect();
+                    println!(
+                        "{}{}: {}",
+                        mesh.tag,
+                        if node.forwarding { " (forwarding)" } else { "" },
+                        if peers.is_empty() {
+                            "-".into()
+                        } else {
+                            peers.join(", ")
+                        }
+                    );
+                }
+            }
+        }
+        Commands::Routes { tag } => {
+            let conf = Conf::new(read_config(args.config.as_ref(), config_format)?);
+            conf.validate()?;
+            let labelled = !conf.meshs.networks.is_empty();
+            let mut found = false;
+            for conf in meshes(conf)?.iter() {
+                let Some(mesh) = conf.meshs.iter().find(|mesh| mesh.tag == tag) else {
+                    continue;
+                };
+                found = true;
+                if labelled {
+                    println!("[{}]", conf.interface);
+                }
+                let routes = conf.routes(mesh);
+                let width = routes
+                    .iter()
+                    .map(|route| route.destination.len())
+                    .max()
+                    .unwrap_or_default();
+                for route in routes.iter() {
+                    println!(
+                        "{:width$} via {} ({})",
+                        route.destination,
+                        route.via.tag,
+                        route.reason,
+                        width = width
+                    );
+                }
+            }
+            if !found {
+                bail!("No mesh tagged `{}`", tag)
+            }
+        }
+        Commands::Schema => {
+            println!("{}", serde_json::to_string_pretty(&Meshs::schema())?);
+        }
         Commands::Append {
             tag,
             in_place,
             count,
         } => {
             let count = count.unwrap_or(1);
-            let mut meshs = read_config(args.config.as_ref())?;
+            let mut meshs = read_config(args.config.as_ref(), config_format)?;
             let c = meshs.meshs.len() as u32 + count;
             if c > 16_777_214 {
                 bail!("Total number of meshes exceed 16,777,214")
@@ -243,11 +613,11 @@ fn main() -> Result<()> {
                 ));
             }
             meshs.meshs = meshs_vec.into_boxed_slice();
-            let json = meshs.to_json()?;
+            let config = serialize_config(&meshs, config_format)?;
             if in_place {
-                fs::write(args.config.as_ref(), json.as_bytes())?;
+                fs::write(args.config.as_ref(), config.as_bytes())?;
             } else {
-                println!("{}", json);
+                println!("{}", config);
             }
         }
     }
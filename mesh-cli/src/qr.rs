@@ -0,0 +1,44 @@
+use anyhow::Result;
+use qrcode::{
+    Color, QrCode,
+    render::{svg, unicode},
+};
+
+const QUIET_ZONE: usize = 4;
+const PNG_SCALE: usize = 8;
+
+pub fn to_utf8(code: &QrCode) -> String {
+    code.render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build()
+}
+
+pub fn to_svg(code: &QrCode) -> String {
+    code.render::<svg::Color>().min_dimensions(256, 256).build()
+}
+
+pub fn to_png(code: &QrCode) -> Result<Vec<u8>> {
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QUIET_ZONE) * PNG_SCALE;
+    let mut pixels = vec![u8::MAX; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color == Color::Light {
+            continue;
+        }
+        let x = (i % modules + QUIET_ZONE) * PNG_SCALE;
+        let y = (i / modules + QUIET_ZONE) * PNG_SCALE;
+        for row in y..y + PNG_SCALE {
+            pixels[row * size + x..row * size + x + PNG_SCALE].fill(0);
+        }
+    }
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(buf)
+}
@@ -1,9 +1,43 @@
-use std::{
-    collections::HashMap,
-    fmt::{self, Write as _},
+mod network_manager;
+mod networkd;
+mod nix;
+mod readme;
+mod router_os;
+mod template;
+mod uci;
+mod wg_quick;
+mod wg_setconf;
+
+use std::{collections::HashMap, fmt, net::IpAddr};
+
+use serde::{Serialize, Serializer, ser::SerializeStruct as _};
+
+use crate::{
+    ip::{contains, overlaps, subnet},
+    mesh::{EndpointBoxStr, Mesh, Meshs, Network, Role},
 };
 
-use crate::mesh::{Mesh, Meshs};
+pub use network_manager::NetworkManager;
+pub use networkd::Networkd;
+pub use nix::Nix;
+pub use readme::Readme;
+pub use router_os::RouterOs;
+pub use template::Template;
+pub use uci::Uci;
+pub use wg_quick::WgQuick;
+pub use wg_setconf::WgSetconf;
+
+pub const DEFAULT_INTERFACE: &str = "wg0";
+
+/// Firewall mark and routing table that keep a client's tunnel traffic off
+/// the default route through its exit node, as wg-quick does.
+const EXIT_TABLE: u32 = 51820;
+
+const DEFAULT_ROUTES: [&str; 2] = ["0.0.0.0/0", "::/0"];
+
+/// Keepalive interval of nodes without an endpoint, which are usually
+/// behind NAT, when the config does not set one.
+pub const DEFAULT_KEEPALIVE: u16 = 25;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -11,8 +45,69 @@ pub enum Error {
     FmtError(#[from] fmt::Error),
     #[error(transparent)]
     SerdeJsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    SerdeYamlError(#[from] serde_norway::Error),
+    #[error(transparent)]
+    TemplateError(#[from] minijinja::Error),
     #[error("duplicate tags: {}", DisplayTags(.0))]
     DuplicateTags(Box<[Box<str>]>),
+    #[error("spokes {} have no reachable hub to peer with", DisplayTags(.0))]
+    NoHub(Box<[Box<str>]>),
+    #[error("route {route} of \"{tag}\" overlaps {other}")]
+    OverlappingRoute {
+        tag: Box<str>,
+        route: Box<str>,
+        other: Box<str>,
+    },
+    #[error("endpoint {endpoint} of \"{tag}\" does not use its listen port {port}")]
+    MismatchedPort {
+        tag: Box<str>,
+        endpoint: Box<str>,
+        port: Box<str>,
+    },
+    #[error("exit node \"{exit_node}\" of \"{tag}\" {reason}")]
+    InvalidExitNode {
+        tag: Box<str>,
+        exit_node: Box<str>,
+        reason: &'static str,
+    },
+    #[error("relay \"{relay}\" {reason}")]
+    InvalidRelay {
+        relay: Box<str>,
+        reason: &'static str,
+    },
+    #[error("{} cannot reach each other and have no relay", DisplayTags(.0))]
+    Unreachable(Box<[Box<str>]>),
+    #[error("member \"{tag}\" of network \"{network}\" {reason}")]
+    InvalidMember {
+        network: Box<str>,
+        tag: Box<str>,
+        reason: &'static str,
+    },
+    #[error("host \"{tag}\" has the address {address} in both \"{network}\" and \"{other}\"")]
+    SharedAddress {
+        tag: Box<str>,
+        address: Box<str>,
+        network: Box<str>,
+        other: Box<str>,
+    },
+    #[error("subnet {subnet} of network \"{network}\" overlaps {other_subnet} of \"{other}\"")]
+    OverlappingNetwork {
+        network: Box<str>,
+        subnet: Box<str>,
+        other: Box<str>,
+        other_subnet: Box<str>,
+    },
+    #[error("network \"{network}\" has the same {what} as \"{other}\"")]
+    NetworkClash {
+        network: Box<str>,
+        other: Box<str>,
+        what: &'static str,
+    },
+    #[error("\"{name}\" is not a valid Kubernetes {what}")]
+    InvalidKubernetesName { name: Box<str>, what: &'static str },
+    #[error("policy references unknown {kind} \"{name}\"")]
+    UnknownReference { kind: &'static str, name: Box<str> },
 }
 
 struct DisplayTags<'a>(&'a [Box<str>]);
@@ -30,87 +125,800 @@ impl<'a> std::fmt::Display for DisplayTags<'a> {
     }
 }
 
-#[derive(Default, Debug)]
+/// One rendered output file of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedFile {
+    /// File name, relative to the output directory.
+    pub name: Box<str>,
+    pub contents: Box<str>,
+    /// Whether the file holds key material and must not be world-readable.
+    pub private: bool,
+}
+
+/// A peer as seen from the node being rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer<'a> {
+    pub mesh: &'a Mesh,
+    /// The peer's endpoint as seen from the node being rendered.
+    pub endpoint: Option<&'a EndpointBoxStr>,
+    pub allowed_ips: Box<[Box<str>]>,
+    /// Keepalive interval in seconds, to keep a NAT mapping towards the
+    /// peer open.
+    pub persistent_keepalive: Option<u16>,
+}
+
+impl Serialize for Peer<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // The peer's private key is deliberately left out.
+        let mut s = serializer.serialize_struct("Peer", 8)?;
+        s.serialize_field("tag", &self.mesh.tag)?;
+        s.serialize_field("pubkey", &self.mesh.key_pair.pubkey)?;
+        s.serialize_field("ipv4", &self.mesh.ipv4)?;
+        s.serialize_field("ipv6", &self.mesh.ipv6)?;
+        s.serialize_field("endpoint", &self.endpoint)?;
+        s.serialize_field("role", &self.mesh.role)?;
+        s.serialize_field("allowed_ips", &self.allowed_ips)?;
+        s.serialize_field("persistent_keepalive", &self.persistent_keepalive)?;
+        s.end()
+    }
+}
+
+/// Serializes the node being rendered with every field, including those the
+/// config file leaves out when they hold their default.
+fn serialize_this<S>(mesh: &&Mesh, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut s = serializer.serialize_struct("Mesh", 12)?;
+    s.serialize_field("tag", &mesh.tag)?;
+    s.serialize_field("pubkey", &mesh.key_pair.pubkey)?;
+    s.serialize_field("prikey", &mesh.key_pair.prikey)?;
+    s.serialize_field("ipv4", &mesh.ipv4)?;
+    s.serialize_field("ipv6", &mesh.ipv6)?;
+    s.serialize_field("endpoint", &mesh.endpoint)?;
+    s.serialize_field("location", &mesh.location)?;
+    s.serialize_field("endpoints", &mesh.endpoints)?;
+    s.serialize_field("role", &mesh.role)?;
+    s.serialize_field("routes", &mesh.routes)?;
+    s.serialize_field("exit_node", &mesh.exit_node)?;
+    s.serialize_field("persistent_keepalive", &mesh.persistent_keepalive)?;
+    s.end()
+}
+
+/// Everything a [`Renderer`] needs to know about a single node.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Node<'a> {
+    #[serde(serialize_with = "serialize_this")]
+    pub this: &'a Mesh,
+    pub interface: &'a str,
+    pub peers: Box<[Peer<'a>]>,
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+    /// Whether the node routes traffic between its peers and needs IP
+    /// forwarding enabled.
+    pub forwarding: bool,
+    /// Tag of the peer the node sends its internet traffic through.
+    pub exit_node: Option<&'a str>,
+    /// Whether the node is an exit node and masquerades mesh traffic.
+    pub masquerade: bool,
+}
+
+impl Node<'_> {
+    /// The IPv4 and IPv6 mesh subnets.
+    pub fn subnets(&self) -> [Box<str>; 2] {
+        [
+            subnet_of(&self.this.ipv4, self.ipv4_prefix, 32),
+            subnet_of(&self.this.ipv6, self.ipv6_prefix, 128),
+        ]
+    }
+
+    /// Peer AllowedIPs outside the mesh subnets, for renderers whose tools
+    /// do not route AllowedIPs by themselves. Default routes toward the exit
+    /// node are left out, they need policy routing.
+    pub fn routes(&self) -> impl Iterator<Item = &str> {
+        let subnets = self.subnets();
+        self.peers
+            .iter()
+            .flat_map(|peer| peer.allowed_ips.iter())
+            .filter(move |ip| {
+                !DEFAULT_ROUTES.contains(&&***ip)
+                    && !subnets.iter().any(|subnet| contains(subnet, ip))
+            })
+            .map(|ip| &**ip)
+    }
+}
+
+/// Turns a [`Node`] into one or more configuration files.
+pub trait Renderer {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error>;
+}
+
+/// Why a destination is routed through a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason<'a> {
+    /// The peer's own mesh addresses.
+    Address,
+    /// The mesh subnet or a node the policy allows, through a spoke's
+    /// gateway.
+    Gateway,
+    /// A network behind the given node.
+    Network(&'a Mesh),
+    /// The given node cannot be reached directly and is relayed.
+    Relay(&'a Mesh),
+    /// Internet traffic, through the exit node.
+    ExitNode,
+}
+
+impl fmt::Display for Reason<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Address => write!(f, "peer address"),
+            Reason::Gateway => write!(f, "through the gateway"),
+            Reason::Network(mesh) => write!(f, "network behind {}", mesh.tag),
+            Reason::Relay(mesh) => write!(f, "relayed to {}", mesh.tag),
+            Reason::ExitNode => write!(f, "exit node"),
+        }
+    }
+}
+
+/// A destination in a node's AllowedIPs and the peer it is routed through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route<'a> {
+    pub destination: Box<str>,
+    pub via: &'a Mesh,
+    pub reason: Reason<'a>,
+}
+
+/// Whether `a` and `b` can handshake, which needs either side to know an
+/// endpoint of the other.
+fn reachable(a: &Mesh, b: &Mesh) -> bool {
+    a.endpoint_for(b).is_some() || b.endpoint_for(a).is_some()
+}
+
+/// The mesh subnet around `addr`, or a host route if it does not parse.
+fn subnet_of(addr: &str, prefix: u8, bits: u8) -> Box<str> {
+    match addr.parse::<IpAddr>() {
+        Ok(addr) => subnet(addr, prefix),
+        Err(_) => format!("{}/{}", addr, bits).into(),
+    }
+}
+
+#[derive(Debug)]
 pub struct Conf {
     pub meshs: Meshs,
+    /// Name of the WireGuard interface on every node.
+    pub interface: Box<str>,
+}
+
+impl Default for Conf {
+    fn default() -> Self {
+        Conf::new(Meshs::default())
+    }
 }
 
 impl Conf {
     pub fn new(meshs: Meshs) -> Self {
-        Conf { meshs }
-    }
-
-    pub fn create_single(&self, this_mesh: &Mesh) -> Result<Box<str>, Error> {
-        let mut config = String::new();
-        writeln!(
-            config,
-            "\
-[Interface]
-# PublicKey = {}
-PrivateKey = {}",
-            this_mesh.key_pair.pubkey, this_mesh.key_pair.prikey,
-        )?;
-        if let Some(e) = &this_mesh.endpoint {
-            writeln!(
-                config,
-                "\
-ListenPort = {}",
-                e.split(':').next_back().unwrap(),
-            )?;
-        }
-        writeln!(
-            config,
-            "\
-Address = {}/{}
-Address = {}/{}",
-            this_mesh.ipv4, self.meshs.ipv4_prefix, this_mesh.ipv6, self.meshs.ipv6_prefix
-        )?;
-        for mesh in self.meshs.iter() {
-            if mesh == this_mesh {
-                continue;
+        Conf {
+            meshs,
+            interface: DEFAULT_INTERFACE.into(),
+        }
+    }
+
+    /// The mesh of a single entry of `networks`: its members with their
+    /// addresses in it and endpoints on its port, on its own interface.
+    pub fn network(&self, network: &Network) -> Result<Conf, Error> {
+        let invalid = |tag: &str, reason| Error::InvalidMember {
+            network: network.name.clone(),
+            tag: tag.into(),
+            reason,
+        };
+        let mut meshs = Vec::with_capacity(network.members.len());
+        for (i, member) in network.members.iter().enumerate() {
+            if network.members[..i].iter().any(|m| m.tag == member.tag) {
+                return Err(invalid(&member.tag, "is listed twice"));
             }
-            writeln!(
-                config,
-                "
-[Peer]
-PublicKey = {}",
-                mesh.key_pair.pubkey
-            )?;
-            if let Some(e) = &mesh.endpoint {
-                writeln!(
-                    config,
-                    "\
-Endpoint = {}",
-                    e
-                )?;
-            }
-            writeln!(
-                config,
-                "\
-AllowedIPs = {}/32, {}/128",
-                mesh.ipv4, mesh.ipv6
-            )?;
-        }
-        Ok(config.into())
-    }
-
-    pub fn create_all(&self) -> Result<HashMap<Box<str>, Box<str>>, Error> {
-        let mut config_map = HashMap::new();
+            let Some(host) = self.meshs.iter().find(|mesh| mesh.tag == member.tag) else {
+                return Err(invalid(&member.tag, "does not exist"));
+            };
+            let port = network.listen_port;
+            meshs.push(Mesh {
+                ipv4: member.ipv4.clone().unwrap_or_else(|| host.ipv4.clone()),
+                ipv6: member.ipv6.clone().unwrap_or_else(|| host.ipv6.clone()),
+                routes: member.routes.clone().unwrap_or_else(|| host.routes.clone()),
+                endpoint: host.endpoint.as_ref().map(|e| e.with_port(port)),
+                endpoints: host
+                    .endpoints
+                    .iter()
+                    .map(|(location, e)| (location.clone(), e.with_port(port)))
+                    .collect(),
+                // An exit node outside of the network is only used elsewhere
+                exit_node: host
+                    .exit_node
+                    .clone()
+                    .filter(|tag| network.members.iter().any(|m| m.tag == *tag)),
+                ..host.clone()
+            });
+        }
+        let has_member = |tag: &str| meshs.iter().any(|mesh| *mesh.tag == *tag);
+        let relay = self.meshs.relay.clone().filter(|tag| has_member(tag));
+        Ok(Conf {
+            meshs: Meshs {
+                meshs: meshs.into(),
+                ipv4_prefix: network.ipv4_prefix,
+                ipv6_prefix: network.ipv6_prefix,
+                relay,
+                networks: Box::default(),
+                ..self.meshs.clone()
+            },
+            interface: network.name.clone(),
+        })
+    }
+
+    /// [`Conf::network`] of every entry of `networks`.
+    pub fn networks(&self) -> Result<Box<[Conf]>, Error> {
+        self.meshs
+            .networks
+            .iter()
+            .map(|network| self.network(network))
+            .collect()
+    }
+
+    /// Whether `a` and `b` get `[Peer]` blocks for each other: spokes only
+    /// peer with hubs, and the policy has to allow the pair.
+    pub fn peers_with(&self, a: &Mesh, b: &Mesh) -> bool {
+        a != b && (a.role == Role::Hub || b.role == Role::Hub) && self.meshs.allows(&a.tag, &b.tag)
+    }
+
+    /// The hub `spoke` routes the whole mesh subnet through, the first one
+    /// it may peer with and can reach. `None` for hubs.
+    pub fn gateway(&self, spoke: &Mesh) -> Option<&Mesh> {
+        if spoke.role != Role::Spoke {
+            return None;
+        }
+        self.meshs.iter().find(|mesh| {
+            mesh.role == Role::Hub && self.peers_with(spoke, mesh) && reachable(spoke, mesh)
+        })
+    }
+
+    /// The nodes `spoke` reaches through `gateway` rather than as peers,
+    /// leaving out those the policy denies.
+    fn behind_gateway<'a>(
+        &'a self,
+        spoke: &'a Mesh,
+        gateway: &'a Mesh,
+    ) -> impl Iterator<Item = &'a Mesh> + Clone {
+        self.meshs.iter().filter(move |mesh| {
+            *mesh != spoke
+                && *mesh != gateway
+                && !(self.peers_with(spoke, mesh) && reachable(spoke, mesh))
+                && self.relay_for(spoke, mesh).is_none()
+                && self.meshs.allows(&spoke.tag, &mesh.tag)
+                && self.peers_with(gateway, mesh)
+        })
+    }
+
+    /// The node relaying between nodes that cannot reach each other: the
+    /// configured one, or else the first node with an endpoint.
+    pub fn relay(&self) -> Option<&Mesh> {
+        match &self.meshs.relay {
+            Some(tag) => self.meshs.iter().find(|mesh| mesh.tag == *tag),
+            None => self.meshs.iter().find(|mesh| mesh.endpoint.is_some()),
+        }
+    }
+
+    /// The relay between `a` and `b`, if they should peer but cannot reach
+    /// each other. The relay has to peer with both.
+    pub fn relay_for(&self, a: &Mesh, b: &Mesh) -> Option<&Mesh> {
+        if !self.peers_with(a, b) || reachable(a, b) {
+            return None;
+        }
+        self.relay().filter(|relay| {
+            *relay != a && *relay != b && self.peers_with(relay, a) && self.peers_with(relay, b)
+        })
+    }
+
+    /// Every destination `this_mesh` routes into the mesh, grouped by the
+    /// peer it goes through, in the order of the config.
+    pub fn routes<'a>(&'a self, this_mesh: &'a Mesh) -> Box<[Route<'a>]> {
+        let gateway = self.gateway(this_mesh);
+        let relay = self.relay();
+        let addresses = |mesh: &Mesh| -> [Box<str>; 2] {
+            [
+                format!("{}/32", mesh.ipv4).into(),
+                format!("{}/128", mesh.ipv6).into(),
+            ]
+        };
+        let mut routes = Vec::new();
+        for via in self
+            .meshs
+            .iter()
+            .filter(|mesh| self.peers_with(this_mesh, mesh) && reachable(this_mesh, mesh))
+        {
+            let mut push = |destinations: &mut dyn Iterator<Item = Box<str>>, reason| {
+                routes.extend(destinations.map(|destination| Route {
+                    destination,
+                    via,
+                    reason,
+                }))
+            };
+            let networks = |mesh: &Mesh| -> Vec<Box<str>> {
+                mesh.routes.iter().map(|route| route.0.clone()).collect()
+            };
+            let behind = (Some(via) == gateway).then(|| self.behind_gateway(this_mesh, via));
+            // The whole subnet only goes through the gateway if the policy
+            // denies none of it, the allowed nodes one by one otherwise
+            let restricted = self
+                .meshs
+                .iter()
+                .any(|mesh| mesh != this_mesh && !self.meshs.allows(&this_mesh.tag, &mesh.tag));
+            if behind.is_some() && !restricted {
+                push(
+                    &mut [
+                        subnet_of(&via.ipv4, self.meshs.ipv4_prefix, 32),
+                        subnet_of(&via.ipv6, self.meshs.ipv6_prefix, 128),
+                    ]
+                    .into_iter(),
+                    Reason::Gateway,
+                );
+            } else {
+                push(&mut addresses(via).into_iter(), Reason::Address);
+            }
+            push(&mut networks(via).into_iter(), Reason::Network(via));
+            if let Some(behind) = behind {
+                if restricted {
+                    for other in behind.clone() {
+                        push(&mut addresses(other).into_iter(), Reason::Gateway);
+                    }
+                }
+                // Other spokes' networks are only reachable through the gateway
+                for other in behind {
+                    push(&mut networks(other).into_iter(), Reason::Network(other));
+                }
+            }
+            if Some(via) == relay {
+                for other in self
+                    .meshs
+                    .iter()
+                    .filter(|other| self.relay_for(this_mesh, other) == Some(via))
+                {
+                    push(
+                        &mut addresses(other).into_iter().chain(networks(other)),
+                        Reason::Relay(other),
+                    );
+                }
+            }
+            if this_mesh.exit_node.as_ref() == Some(&via.tag) {
+                push(
+                    &mut DEFAULT_ROUTES.into_iter().map(Box::from),
+                    Reason::ExitNode,
+                );
+            }
+        }
+        routes.into()
+    }
+
+    /// The keepalive interval of `this_mesh`: its own, or the mesh-wide one
+    /// if it has no endpoint.
+    pub fn keepalive(&self, this_mesh: &Mesh) -> Option<u16> {
+        this_mesh
+            .persistent_keepalive
+            .or_else(|| {
+                this_mesh
+                    .endpoint
+                    .is_none()
+                    .then(|| self.meshs.persistent_keepalive.unwrap_or(DEFAULT_KEEPALIVE))
+            })
+            .filter(|&interval| interval != 0)
+    }
+
+    pub fn node<'a>(&'a self, this_mesh: &'a Mesh) -> Node<'a> {
+        let routes = self.routes(this_mesh);
+        let keepalive = self.keepalive(this_mesh);
+        let peers = self
+            .meshs
+            .iter()
+            .filter(|mesh| self.peers_with(this_mesh, mesh) && reachable(this_mesh, mesh))
+            .map(|mesh| Peer {
+                mesh,
+                endpoint: mesh.endpoint_for(this_mesh),
+                allowed_ips: routes
+                    .iter()
+                    .filter(|route| route.via == mesh)
+                    .map(|route| route.destination.clone())
+                    .collect(),
+                // Only a peer with an endpoint can be reached to keep alive
+                persistent_keepalive: keepalive.filter(|_| mesh.endpoint_for(this_mesh).is_some()),
+            })
+            .collect();
+        let masquerade = self.meshs.iter().any(|mesh| {
+            mesh.exit_node.as_ref() == Some(&this_mesh.tag) && self.peers_with(mesh, this_mesh)
+        });
+        let relaying = self.relay() == Some(this_mesh)
+            && self.meshs.iter().enumerate().any(|(i, a)| {
+                self.meshs[i + 1..]
+                    .iter()
+                    .any(|b| self.relay_for(a, b).is_some())
+            });
+        let forwarding = masquerade
+            || relaying
+            || !this_mesh.routes.is_empty()
+            || self.meshs.iter().any(|mesh| {
+                self.gateway(mesh) == Some(this_mesh)
+                    && self.behind_gateway(mesh, this_mesh).next().is_some()
+            });
+        Node {
+            this: this_mesh,
+            interface: &self.interface,
+            peers,
+            ipv4_prefix: self.meshs.ipv4_prefix,
+            ipv6_prefix: self.meshs.ipv6_prefix,
+            forwarding,
+            exit_node: this_mesh.exit_node.as_deref(),
+            masquerade,
+        }
+    }
+
+    pub fn create_single(
+        &self,
+        renderer: &dyn Renderer,
+        this_mesh: &Mesh,
+    ) -> Result<Box<[RenderedFile]>, Error> {
+        renderer.render(&self.node(this_mesh))
+    }
+
+    /// Fails if several meshes share a tag.
+    pub fn check_tags(&self) -> Result<(), Error> {
         let mut tag_counts: HashMap<_, usize> = HashMap::new();
         for mesh in self.meshs.iter() {
-            let this_tag = mesh.tag.clone();
-            *tag_counts.entry(this_tag.clone()).or_insert(0) += 1;
-            config_map.insert(this_tag, self.create_single(mesh)?);
+            *tag_counts.entry(&mesh.tag).or_insert(0) += 1;
         }
         let duplicates: Box<[_]> = tag_counts
             .into_iter()
             .filter(|(_, count)| *count > 1)
-            .map(|(tag, _)| tag)
+            .map(|(tag, _)| tag.clone())
             .collect();
         if duplicates.is_empty() {
-            Ok(config_map)
+            Ok(())
         } else {
             Err(Error::DuplicateTags(duplicates))
         }
     }
+
+    /// Fails if a spoke has no hub it may peer with and can reach.
+    pub fn check_roles(&self) -> Result<(), Error> {
+        let spokes: Box<[_]> = self
+            .meshs
+            .iter()
+            .filter(|mesh| mesh.role == Role::Spoke && self.gateway(mesh).is_none())
+            .map(|mesh| mesh.tag.clone())
+            .collect();
+        if spokes.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::NoHub(spokes))
+        }
+    }
+
+    /// Fails if the policy names a tag or group that does not exist.
+    pub fn check_policy(&self) -> Result<(), Error> {
+        let Some(policy) = &self.meshs.policy else {
+            return Ok(());
+        };
+        let unknown = |kind, name: &str| Error::UnknownReference {
+            kind,
+            name: name.into(),
+        };
+        let has_tag = |tag: &str| self.meshs.iter().any(|mesh| *mesh.tag == *tag);
+        for tag in policy.groups.values().flat_map(|tags| tags.iter()) {
+            if !has_tag(tag) {
+                return Err(unknown("tag", tag));
+            }
+        }
+        for selector in policy.rules.iter().flat_map(|rule| rule.between.iter()) {
+            match selector.strip_prefix('@') {
+                Some(group) if !policy.groups.contains_key(group) => {
+                    return Err(unknown("group", group));
+                }
+                None if !has_tag(selector) => return Err(unknown("tag", selector)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a route overlaps the mesh subnet or a route of another node.
+    pub fn check_routes(&self) -> Result<(), Error> {
+        for (i, mesh) in self.meshs.iter().enumerate() {
+            let subnets = [
+                subnet_of(&mesh.ipv4, self.meshs.ipv4_prefix, 32),
+                subnet_of(&mesh.ipv6, self.meshs.ipv6_prefix, 128),
+            ];
+            let others = self.meshs[i + 1..]
+                .iter()
+                .flat_map(|other| other.routes.iter().map(move |route| (other, route)));
+            let overlapping = |route: &str| {
+                if let Some(subnet) = subnets.iter().find(|subnet| overlaps(route, subnet)) {
+                    return Some(format!("the mesh subnet {}", subnet));
+                }
+                others
+                    .clone()
+                    .find(|(_, other)| overlaps(route, other))
+                    .map(|(other, other_route)| format!("{} of \"{}\"", other_route, other.tag))
+            };
+            for route in mesh.routes.iter() {
+                if let Some(other) = overlapping(route) {
+                    return Err(Error::OverlappingRoute {
+                        tag: mesh.tag.clone(),
+                        route: route.0.clone(),
+                        other: other.into(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the endpoints of a node disagree on the port it listens on.
+    pub fn check_endpoints(&self) -> Result<(), Error> {
+        for mesh in self.meshs.iter() {
+            let Some(port) = mesh.listen_port() else {
+                continue;
+            };
+            if let Some(endpoint) = mesh.endpoints.values().find(|e| e.port() != port) {
+                return Err(Error::MismatchedPort {
+                    tag: mesh.tag.clone(),
+                    endpoint: endpoint.to_string().into(),
+                    port: port.into(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if an exit node is not a peer of the node picking it.
+    pub fn check_exit_nodes(&self) -> Result<(), Error> {
+        for mesh in self.meshs.iter() {
+            let Some(exit_node) = &mesh.exit_node else {
+                continue;
+            };
+            let reason = match self.meshs.iter().find(|other| other.tag == *exit_node) {
+                None => "does not exist",
+                Some(other) if other == mesh => "is the node itself",
+                Some(other) if !self.peers_with(mesh, other) => "is not a peer",
+                Some(_) => continue,
+            };
+            return Err(Error::InvalidExitNode {
+                tag: mesh.tag.clone(),
+                exit_node: exit_node.clone(),
+                reason,
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails if the configured relay is unusable, or if two nodes should
+    /// peer but cannot reach each other and have no relay.
+    pub fn check_relay(&self) -> Result<(), Error> {
+        if let Some(tag) = &self.meshs.relay {
+            let reason = match self.relay() {
+                None => Some("does not exist"),
+                Some(relay) if relay.endpoint.is_none() => Some("has no endpoint"),
+                Some(_) => None,
+            };
+            if let Some(reason) = reason {
+                return Err(Error::InvalidRelay {
+                    relay: tag.clone(),
+                    reason,
+                });
+            }
+        }
+        for (i, a) in self.meshs.iter().enumerate() {
+            for b in self.meshs[i + 1..].iter() {
+                if self.peers_with(a, b) && !reachable(a, b) && self.relay_for(a, b).is_none() {
+                    return Err(Error::Unreachable(Box::new([a.tag.clone(), b.tag.clone()])));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs every check a config has to pass before it is rendered.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.check_tags()?;
+        self.check_policy()?;
+        if self.meshs.networks.is_empty() {
+            self.check_nodes()
+        } else {
+            self.check_networks()?;
+            self.networks()?.iter().try_for_each(Conf::check_nodes)
+        }
+    }
+
+    /// Fails if two networks share a name or a listen port, if members of a
+    /// network share an address or sit outside of its subnet, if a host has
+    /// the same address in two networks, or if the subnets or routes of two
+    /// networks overlap.
+    pub fn check_networks(&self) -> Result<(), Error> {
+        let networks = &self.meshs.networks;
+        for (i, network) in networks.iter().enumerate() {
+            for other in networks[..i].iter() {
+                let what = if other.name == network.name {
+                    "name"
+                } else if other.listen_port == network.listen_port {
+                    "listen port"
+                } else {
+                    continue;
+                };
+                return Err(Error::NetworkClash {
+                    network: network.name.clone(),
+                    other: other.name.clone(),
+                    what,
+                });
+            }
+        }
+        let networks = self.networks()?;
+        let parse = |addr: &str| addr.parse::<IpAddr>().ok();
+        for network in networks.iter() {
+            let meshs = &network.meshs;
+            let Some(first) = meshs.first() else {
+                continue;
+            };
+            let subnets = |mesh: &Mesh| {
+                [
+                    subnet_of(&mesh.ipv4, meshs.ipv4_prefix, 32),
+                    subnet_of(&mesh.ipv6, meshs.ipv6_prefix, 128),
+                ]
+            };
+            for (i, mesh) in meshs.iter().enumerate() {
+                let invalid = |reason| Error::InvalidMember {
+                    network: network.interface.clone(),
+                    tag: mesh.tag.clone(),
+                    reason,
+                };
+                if meshs[..i].iter().any(|other| {
+                    parse(&other.ipv4) == parse(&mesh.ipv4)
+                        || parse(&other.ipv6) == parse(&mesh.ipv6)
+                }) {
+                    return Err(invalid("reuses the address of another member"));
+                }
+                if subnets(mesh) != subnets(first) {
+                    return Err(invalid("is outside of the network's subnet"));
+                }
+            }
+        }
+        for (i, network) in networks.iter().enumerate() {
+            for other in networks[..i].iter() {
+                for mesh in network.meshs.iter() {
+                    let Some(same) = other.meshs.iter().find(|same| same.tag == mesh.tag) else {
+                        continue;
+                    };
+                    let address = if parse(&same.ipv4) == parse(&mesh.ipv4) {
+                        &*mesh.ipv4
+                    } else if parse(&same.ipv6) == parse(&mesh.ipv6) {
+                        &*mesh.ipv6
+                    } else {
+                        continue;
+                    };
+                    return Err(Error::SharedAddress {
+                        tag: mesh.tag.clone(),
+                        address: address.into(),
+                        network: network.interface.clone(),
+                        other: other.interface.clone(),
+                    });
+                }
+            }
+        }
+        // Every network is a subnet of its own, so the first member stands
+        // for all of them
+        let subnets: Box<[_]> = networks
+            .iter()
+            .map(|network| match network.meshs.first() {
+                Some(first) => [
+                    subnet_of(&first.ipv4, network.meshs.ipv4_prefix, 32),
+                    subnet_of(&first.ipv6, network.meshs.ipv6_prefix, 128),
+                ],
+                None => Default::default(),
+            })
+            .collect();
+        for (i, network) in networks.iter().enumerate() {
+            for (j, other) in networks[..i].iter().enumerate() {
+                for (subnet, other_subnet) in subnets[i].iter().zip(subnets[j].iter()) {
+                    if overlaps(subnet, other_subnet) {
+                        return Err(Error::OverlappingNetwork {
+                            network: network.interface.clone(),
+                            subnet: subnet.clone(),
+                            other: other.interface.clone(),
+                            other_subnet: other_subnet.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        for (i, network) in networks.iter().enumerate() {
+            for (j, other) in networks.iter().enumerate().filter(|(j, _)| *j != i) {
+                let other_routes = other
+                    .meshs
+                    .iter()
+                    .flat_map(|mesh| mesh.routes.iter().map(move |route| (mesh, route)));
+                let overlapping = |route: &str| {
+                    if let Some(subnet) = subnets[j].iter().find(|subnet| overlaps(route, subnet)) {
+                        return Some(format!("the subnet {} of \"{}\"", subnet, other.interface));
+                    }
+                    other_routes
+                        .clone()
+                        .find(|(_, other_route)| overlaps(route, other_route))
+                        .map(|(owner, other_route)| {
+                            format!(
+                                "{} of \"{}\" in \"{}\"",
+                                other_route, owner.tag, other.interface
+                            )
+                        })
+                };
+                for mesh in network.meshs.iter() {
+                    for route in mesh.routes.iter() {
+                        if let Some(other) = overlapping(route) {
+                            return Err(Error::OverlappingRoute {
+                                tag: mesh.tag.clone(),
+                                route: route.0.clone(),
+                                other: other.into(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The checks that apply to each mesh on its own.
+    fn check_nodes(&self) -> Result<(), Error> {
+        self.check_roles()?;
+        self.check_routes()?;
+        self.check_endpoints()?;
+        self.check_exit_nodes()?;
+        self.check_relay()
+    }
+
+    pub fn create_all(
+        &self,
+        renderer: &dyn Renderer,
+    ) -> Result<HashMap<Box<str>, Box<[RenderedFile]>>, Error> {
+        self.validate()?;
+        if !self.meshs.networks.is_empty() {
+            return self.create_all_networks(renderer);
+        }
+        let mut config_map = HashMap::new();
+        for mesh in self.meshs.iter() {
+            config_map.insert(mesh.tag.clone(), self.create_single(renderer, mesh)?);
+        }
+        Ok(config_map)
+    }
+
+    /// Renders every member of every network, each network's files in a
+    /// directory named after its interface.
+    fn create_all_networks(
+        &self,
+        renderer: &dyn Renderer,
+    ) -> Result<HashMap<Box<str>, Box<[RenderedFile]>>, Error> {
+        let mut config_map: HashMap<Box<str>, Vec<RenderedFile>> = HashMap::new();
+        for network in self.networks()?.iter() {
+            for mesh in network.meshs.iter() {
+                let files = network.create_single(renderer, mesh)?;
+                config_map
+                    .entry(mesh.tag.clone())
+                    .or_default()
+                    .extend(files.into_iter().map(|file| RenderedFile {
+                        name: format!("{}/{}", network.interface, file.name).into(),
+                        ..file
+                    }));
+            }
+        }
+        Ok(config_map
+            .into_iter()
+            .map(|(tag, files)| (tag, files.into()))
+            .collect())
+    }
 }
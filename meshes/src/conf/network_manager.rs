@@ -0,0 +1,66 @@
+use std::fmt::Write as _;
+
+use crate::conf::{Error, Node, RenderedFile, Renderer};
+
+/// Renders a NetworkManager keyfile (`.nmconnection`) per node.
+///
+/// NetworkManager refuses to load keyfiles readable by anyone but root, so
+/// the output is always marked private. Keyfiles cannot turn on IP
+/// forwarding or masquerading, a forwarding hub or exit node needs them set
+/// up separately.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NetworkManager;
+
+impl Renderer for NetworkManager {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let mut config = String::new();
+        writeln!(
+            config,
+            "\
+[connection]
+id={}
+type=wireguard
+interface-name={}
+
+[wireguard]
+# public-key={}
+private-key={}",
+            node.interface, node.interface, this_mesh.key_pair.pubkey, this_mesh.key_pair.prikey,
+        )?;
+        if let Some(port) = this_mesh.listen_port() {
+            writeln!(config, "listen-port={}", port)?;
+        }
+        for peer in node.peers.iter() {
+            writeln!(config, "\n[wireguard-peer.{}]", peer.mesh.key_pair.pubkey)?;
+            if let Some(e) = peer.endpoint {
+                writeln!(config, "endpoint={}", e)?;
+            }
+            write!(config, "allowed-ips=")?;
+            for ip in peer.allowed_ips.iter() {
+                write!(config, "{};", ip)?;
+            }
+            writeln!(config)?;
+            if let Some(interval) = peer.persistent_keepalive {
+                writeln!(config, "persistent-keepalive={}", interval)?;
+            }
+        }
+        writeln!(
+            config,
+            "
+[ipv4]
+address1={}/{}
+method=manual
+
+[ipv6]
+address1={}/{}
+method=manual",
+            this_mesh.ipv4, node.ipv4_prefix, this_mesh.ipv6, node.ipv6_prefix
+        )?;
+        Ok(Box::new([RenderedFile {
+            name: format!("{}.nmconnection", this_mesh.tag).into(),
+            contents: config.into(),
+            private: true,
+        }]))
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write as _;
+
+use crate::conf::{EXIT_TABLE, Error, Node, RenderedFile, Renderer};
+
+/// Renders a systemd-networkd `.netdev`/`.network` pair per node.
+///
+/// The private key is written to a separate `<tag>.key` file, which is
+/// expected to be installed as `<key_dir>/<interface>.key`, owned by
+/// `root:systemd-network` with mode `0640`.
+#[derive(Debug, Clone)]
+pub struct Networkd {
+    pub key_dir: Box<str>,
+}
+
+impl Default for Networkd {
+    fn default() -> Self {
+        Networkd {
+            key_dir: "/etc/systemd/network".into(),
+        }
+    }
+}
+
+impl Renderer for Networkd {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let mut netdev = String::new();
+        writeln!(
+            netdev,
+            "\
+[NetDev]
+Name={}
+Kind=wireguard
+
+[WireGuard]
+# PublicKey={}
+PrivateKeyFile={}/{}.key",
+            node.interface,
+            this_mesh.key_pair.pubkey,
+            self.key_dir.trim_end_matches('/'),
+            node.interface,
+        )?;
+        if let Some(port) = this_mesh.listen_port() {
+            writeln!(netdev, "ListenPort={}", port)?;
+        }
+        if node.exit_node.is_some() {
+            writeln!(netdev, "FirewallMark={}", EXIT_TABLE)?;
+        }
+        for peer in node.peers.iter() {
+            writeln!(
+                netdev,
+                "
+[WireGuardPeer]
+PublicKey={}",
+                peer.mesh.key_pair.pubkey
+            )?;
+            if let Some(e) = peer.endpoint {
+                writeln!(netdev, "Endpoint={}", e)?;
+            }
+            writeln!(netdev, "AllowedIPs={}", peer.allowed_ips.join(", "))?;
+            if let Some(interval) = peer.persistent_keepalive {
+                writeln!(netdev, "PersistentKeepalive={}", interval)?;
+            }
+        }
+
+        let mut network = String::new();
+        writeln!(
+            network,
+            "\
+[Match]
+Name={}
+
+[Network]
+Address={}/{}
+Address={}/{}",
+            node.interface, this_mesh.ipv4, node.ipv4_prefix, this_mesh.ipv6, node.ipv6_prefix
+        )?;
+        if node.forwarding {
+            writeln!(network, "IPForward=yes")?;
+        }
+        if node.masquerade {
+            writeln!(network, "IPMasquerade=both")?;
+        }
+        for route in node.routes() {
+            writeln!(network, "\n[Route]\nDestination={}", route)?;
+        }
+        if node.exit_node.is_some() {
+            // Everything but the tunnel itself goes through the exit node,
+            // unless main has a more specific route
+            writeln!(
+                network,
+                "
+[RoutingPolicyRule]
+FirewallMark={}
+InvertRule=yes
+Table={}
+Family=both
+Priority=32765
+
+[RoutingPolicyRule]
+Table=main
+SuppressPrefixLength=0
+Family=both
+Priority=32764
+
+[Route]
+Destination=0.0.0.0/0
+Table={}
+
+[Route]
+Destination=::/0
+Table={}",
+                EXIT_TABLE, EXIT_TABLE, EXIT_TABLE, EXIT_TABLE
+            )?;
+        }
+
+        Ok(Box::new([
+            RenderedFile {
+                name: format!("{}.netdev", this_mesh.tag).into(),
+                contents: netdev.into(),
+                private: false,
+            },
+            RenderedFile {
+                name: format!("{}.network", this_mesh.tag).into(),
+                contents: network.into(),
+                private: false,
+            },
+            RenderedFile {
+                name: format!("{}.key", this_mesh.tag).into(),
+                contents: format!("{}\n", this_mesh.key_pair.prikey).into(),
+                private: true,
+            },
+        ]))
+    }
+}
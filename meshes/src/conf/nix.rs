@@ -0,0 +1,164 @@
+use std::fmt::{self, Write as _};
+
+use crate::conf::{EXIT_TABLE, Error, Node, RenderedFile, Renderer};
+
+/// Renders a NixOS module setting `networking.wireguard.interfaces` per node.
+///
+/// The module only references the private key through `privateKeyFile`, so
+/// it is safe to commit. The key itself is written to a separate
+/// `<tag>.nix.key` file, to be installed as `<key_dir>/<interface>.key`.
+#[derive(Debug, Clone)]
+pub struct Nix {
+    pub key_dir: Box<str>,
+}
+
+impl Default for Nix {
+    fn default() -> Self {
+        Nix {
+            key_dir: "/etc/wireguard".into(),
+        }
+    }
+}
+
+struct NixStr<'a>(&'a str);
+
+impl fmt::Display for NixStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        let mut chars = self.0.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' | '\\' => write!(f, "\\{}", c)?,
+                '$' if chars.peek() == Some(&'{') => f.write_str("\\$")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+struct NixList<'a, T>(&'a [T]);
+
+impl<T: AsRef<str>> fmt::Display for NixList<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('[')?;
+        for item in self.0 {
+            write!(f, " {}", NixStr(item.as_ref()))?;
+        }
+        f.write_str(" ]")
+    }
+}
+
+impl Renderer for Nix {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let ips = [
+            format!("{}/{}", this_mesh.ipv4, node.ipv4_prefix),
+            format!("{}/{}", this_mesh.ipv6, node.ipv6_prefix),
+        ];
+        let mut module = String::new();
+        writeln!(
+            module,
+            "\
+{{
+  networking.wireguard.interfaces.{} = {{
+    # publicKey = {}
+    ips = {};
+    privateKeyFile = {};",
+            NixStr(node.interface),
+            NixStr(&this_mesh.key_pair.pubkey),
+            NixList(&ips),
+            NixStr(&format!(
+                "{}/{}.key",
+                self.key_dir.trim_end_matches('/'),
+                node.interface
+            )),
+        )?;
+        if let Some(port) = this_mesh.listen_port() {
+            writeln!(module, "    listenPort = {};", port)?;
+        }
+        if node.exit_node.is_some() {
+            // Like wg-quick, route everything but the tunnel itself through
+            // the exit node
+            let rules = |action| {
+                ["-4", "-6"]
+                    .map(|family| {
+                        format!(
+                            "ip {} rule {} not fwmark {} table {}; ip {} rule {} table main suppress_prefixlength 0",
+                            family, action, EXIT_TABLE, EXIT_TABLE, family, action
+                        )
+                    })
+                    .join("; ")
+            };
+            writeln!(
+                module,
+                "    fwMark = {};
+    table = {};
+    postSetup = {};
+    postShutdown = {};",
+                NixStr(&EXIT_TABLE.to_string()),
+                NixStr(&EXIT_TABLE.to_string()),
+                NixStr(&rules("add")),
+                NixStr(&rules("del")),
+            )?;
+        }
+        writeln!(module, "    peers = [")?;
+        for peer in node.peers.iter() {
+            writeln!(
+                module,
+                "      {{
+        # {}
+        publicKey = {};
+        allowedIPs = {};",
+                peer.mesh.tag,
+                NixStr(&peer.mesh.key_pair.pubkey),
+                NixList(&peer.allowed_ips)
+            )?;
+            if let Some(e) = peer.endpoint {
+                writeln!(module, "        endpoint = {};", NixStr(e))?;
+            }
+            if let Some(interval) = peer.persistent_keepalive {
+                writeln!(module, "        persistentKeepalive = {};", interval)?;
+            }
+            writeln!(module, "      }}")?;
+        }
+        writeln!(
+            module,
+            "    ];
+  }};"
+        )?;
+        if node.forwarding {
+            writeln!(
+                module,
+                "  boot.kernel.sysctl.\"net.ipv4.ip_forward\" = 1;
+  boot.kernel.sysctl.\"net.ipv6.conf.all.forwarding\" = 1;"
+            )?;
+        }
+        if node.masquerade {
+            writeln!(
+                module,
+                "  networking.nat = {{
+    enable = true;
+    enableIPv6 = true;
+    internalInterfaces = [ {} ];
+  }};",
+                NixStr(node.interface)
+            )?;
+        }
+        writeln!(module, "}}")?;
+
+        Ok(Box::new([
+            RenderedFile {
+                name: format!("{}.nix", this_mesh.tag).into(),
+                contents: module.into(),
+                private: false,
+            },
+            RenderedFile {
+                name: format!("{}.nix.key", this_mesh.tag).into(),
+                contents: format!("{}\n", this_mesh.key_pair.prikey).into(),
+                private: true,
+            },
+        ]))
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::Write as _;
+
+use crate::conf::{Error, Node, RenderedFile, Renderer};
+
+/// Renders a human-readable summary of a node's addresses and peers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Readme;
+
+impl Renderer for Readme {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let mut readme = String::new();
+        writeln!(
+            readme,
+            "\
+# {}
+
+- Interface: {}
+- Public key: {}
+- IPv4: {}/{}
+- IPv6: {}/{}",
+            this_mesh.tag,
+            node.interface,
+            this_mesh.key_pair.pubkey,
+            this_mesh.ipv4,
+            node.ipv4_prefix,
+            this_mesh.ipv6,
+            node.ipv6_prefix
+        )?;
+        if let Some(e) = &this_mesh.endpoint {
+            writeln!(readme, "- Endpoint: {}", e)?;
+        }
+        for (location, e) in this_mesh.endpoints.iter() {
+            writeln!(readme, "- Endpoint in {}: {}", location, e)?;
+        }
+        if !this_mesh.routes.is_empty() {
+            let routes: Box<[_]> = this_mesh.routes.iter().map(|route| &**route).collect();
+            writeln!(readme, "- Routes: {}", routes.join(", "))?;
+        }
+        if let Some(exit_node) = node.exit_node {
+            writeln!(readme, "- Exit node: {}", exit_node)?;
+        }
+        if node.forwarding {
+            writeln!(readme, "- Forwards traffic for its peers")?;
+        }
+        if node.masquerade {
+            writeln!(readme, "- Masquerades internet traffic of its peers")?;
+        }
+        writeln!(
+            readme,
+            "
+## Peers
+
+| Tag | Public key | Endpoint | Allowed IPs | Keepalive |
+| --- | --- | --- | --- | --- |"
+        )?;
+        for peer in node.peers.iter() {
+            writeln!(
+                readme,
+                "| {} | {} | {} | {} | {} |",
+                peer.mesh.tag,
+                peer.mesh.key_pair.pubkey,
+                peer.endpoint.map(|e| &**e).unwrap_or("-"),
+                peer.allowed_ips.join(", "),
+                peer.persistent_keepalive
+                    .map_or("-".into(), |interval| format!("{}s", interval))
+            )?;
+        }
+        Ok(Box::new([RenderedFile {
+            name: format!("{}.README.md", this_mesh.tag).into(),
+            contents: readme.into(),
+            private: false,
+        }]))
+    }
+}
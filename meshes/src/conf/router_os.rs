@@ -0,0 +1,100 @@
+use std::fmt::{self, Write as _};
+
+use crate::conf::{Error, Node, RenderedFile, Renderer};
+
+/// Renders a RouterOS 7 script (`.rsc`) per node.
+///
+/// A client's default route through its exit node needs routing marks that
+/// depend on the rest of the router's config, so it is left out.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RouterOs;
+
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            if matches!(c, '"' | '\\' | '$' | '?') {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        f.write_char('"')
+    }
+}
+
+impl Renderer for RouterOs {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let interface = Quoted(node.interface);
+        let mut script = String::new();
+        write!(
+            script,
+            "/interface wireguard add name={} private-key={}",
+            interface,
+            Quoted(&this_mesh.key_pair.prikey)
+        )?;
+        if let Some(port) = this_mesh.listen_port() {
+            write!(script, " listen-port={}", port)?;
+        }
+        writeln!(script)?;
+        for peer in node.peers.iter() {
+            write!(
+                script,
+                "/interface wireguard peers add interface={} comment={} public-key={}",
+                interface,
+                Quoted(&peer.mesh.tag),
+                Quoted(&peer.mesh.key_pair.pubkey)
+            )?;
+            if let Some(e) = peer.endpoint {
+                write!(
+                    script,
+                    " endpoint-address={} endpoint-port={}",
+                    Quoted(e.host()),
+                    e.port()
+                )?;
+            }
+            if let Some(interval) = peer.persistent_keepalive {
+                write!(script, " persistent-keepalive={}s", interval)?;
+            }
+            writeln!(script, " allowed-address={}", peer.allowed_ips.join(","))?;
+        }
+        writeln!(
+            script,
+            "\
+/ip address add address={}/{} interface={}
+/ipv6 address add address={}/{} interface={} advertise=no",
+            this_mesh.ipv4,
+            node.ipv4_prefix,
+            interface,
+            this_mesh.ipv6,
+            node.ipv6_prefix,
+            interface
+        )?;
+        for route in node.routes() {
+            let family = if route.contains(':') { "ipv6" } else { "ip" };
+            writeln!(
+                script,
+                "/{} route add dst-address={} gateway={}",
+                family, route, interface
+            )?;
+        }
+        if node.masquerade {
+            for (family, subnet) in ["ip", "ipv6"].into_iter().zip(node.subnets()) {
+                writeln!(
+                    script,
+                    "/{} firewall nat add chain=srcnat src-address={} out-interface={} action=masquerade",
+                    family,
+                    subnet,
+                    Quoted(&format!("!{}", node.interface))
+                )?;
+            }
+        }
+        Ok(Box::new([RenderedFile {
+            name: format!("{}.rsc", this_mesh.tag).into(),
+            contents: script.into(),
+            private: true,
+        }]))
+    }
+}
@@ -0,0 +1,47 @@
+use minijinja::{Environment, UndefinedBehavior};
+
+use crate::conf::{Error, Node, RenderedFile, Renderer};
+
+/// Renders each node through a user-supplied [minijinja] template.
+///
+/// The template sees the [`Node`] as its context: `this`, `interface`,
+/// `peers`, `ipv4_prefix` and `ipv6_prefix`. Peers never expose their
+/// private keys.
+#[derive(Debug)]
+pub struct Template {
+    env: Environment<'static>,
+    name: Box<str>,
+    extension: Box<str>,
+}
+
+impl Template {
+    /// Parses `source`, reporting syntax errors against `name`. Output files
+    /// are named `<tag>.<extension>`.
+    pub fn new(
+        name: impl Into<Box<str>>,
+        source: impl Into<Box<str>>,
+        extension: impl Into<Box<str>>,
+    ) -> Result<Self, Error> {
+        let name = name.into();
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.add_template_owned(name.to_string(), source.into().into_string())?;
+        Ok(Template {
+            env,
+            name,
+            extension: extension.into(),
+        })
+    }
+}
+
+impl Renderer for Template {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let contents = self.env.get_template(&self.name)?.render(node)?;
+        Ok(Box::new([RenderedFile {
+            name: format!("{}.{}", node.this.tag, self.extension).into(),
+            contents: contents.into(),
+            private: true,
+        }]))
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt::{self, Write as _};
+
+use crate::conf::{Error, Node, RenderedFile, Renderer};
+
+/// Renders OpenWrt `/etc/config/network` sections per node.
+///
+/// Two files are produced: `<tag>.uci`, to be merged into
+/// `/etc/config/network`, and `<tag>.batch`, the same sections as
+/// `uci batch` commands. Masquerading on an exit node belongs to the
+/// firewall config and is left out.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Uci;
+
+struct Section {
+    kind: Box<str>,
+    name: Option<Box<str>>,
+    options: Vec<(&'static str, Box<str>)>,
+    lists: Vec<(&'static str, Box<str>)>,
+}
+
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}'", self.0.replace('\'', r"'\''"))
+    }
+}
+
+impl Renderer for Uci {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let mut sections = Vec::with_capacity(node.peers.len() + 1);
+        let mut interface = Section {
+            kind: "interface".into(),
+            name: Some(node.interface.into()),
+            options: vec![
+                ("proto", "wireguard".into()),
+                ("private_key", this_mesh.key_pair.prikey.clone()),
+            ],
+            lists: vec![
+                (
+                    "addresses",
+                    format!("{}/{}", this_mesh.ipv4, node.ipv4_prefix).into(),
+                ),
+                (
+                    "addresses",
+                    format!("{}/{}", this_mesh.ipv6, node.ipv6_prefix).into(),
+                ),
+            ],
+        };
+        if let Some(port) = this_mesh.listen_port() {
+            interface.options.push(("listen_port", port.into()));
+        }
+        sections.push(interface);
+        for peer in node.peers.iter() {
+            let mut options = vec![
+                ("description", peer.mesh.tag.clone()),
+                ("public_key", peer.mesh.key_pair.pubkey.clone()),
+            ];
+            if let Some(e) = peer.endpoint {
+                options.push(("endpoint_host", e.host().into()));
+                options.push(("endpoint_port", e.port().into()));
+            }
+            if let Some(interval) = peer.persistent_keepalive {
+                options.push(("persistent_keepalive", interval.to_string().into()));
+            }
+            options.push(("route_allowed_ips", "1".into()));
+            sections.push(Section {
+                kind: format!("wireguard_{}", node.interface).into(),
+                name: None,
+                options,
+                lists: peer
+                    .allowed_ips
+                    .iter()
+                    .map(|ip| ("allowed_ips", ip.clone()))
+                    .collect(),
+            });
+        }
+
+        let mut config = String::new();
+        let mut batch = String::new();
+        for section in sections.iter() {
+            if !config.is_empty() {
+                writeln!(config)?;
+            }
+            let path = match &section.name {
+                Some(name) => {
+                    writeln!(config, "config {} {}", section.kind, Quoted(name))?;
+                    writeln!(batch, "set network.{}={}", name, section.kind)?;
+                    format!("network.{}", name)
+                }
+                None => {
+                    writeln!(config, "config {}", section.kind)?;
+                    writeln!(batch, "add network {}", section.kind)?;
+                    format!("network.@{}[-1]", section.kind)
+                }
+            };
+            for (key, value) in section.options.iter() {
+                writeln!(config, "\toption {} {}", key, Quoted(value))?;
+                writeln!(batch, "set {}.{}={}", path, key, Quoted(value))?;
+            }
+            for (key, value) in section.lists.iter() {
+                writeln!(config, "\tlist {} {}", key, Quoted(value))?;
+                writeln!(batch, "add_list {}.{}={}", path, key, Quoted(value))?;
+            }
+        }
+        writeln!(batch, "commit network")?;
+
+        Ok(Box::new([
+            RenderedFile {
+                name: format!("{}.uci", this_mesh.tag).into(),
+                contents: config.into(),
+                private: true,
+            },
+            RenderedFile {
+                name: format!("{}.batch", this_mesh.tag).into(),
+                contents: batch.into(),
+                private: true,
+            },
+        ]))
+    }
+}
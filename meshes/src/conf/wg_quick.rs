@@ -0,0 +1,104 @@
+use std::fmt::{self, Write as _};
+
+use crate::conf::{Error, Node, RenderedFile, Renderer};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WgQuick;
+
+/// Writes the `[Interface]` keys understood by both wg-quick and `wg setconf`.
+pub(super) fn write_interface(config: &mut String, node: &Node<'_>) -> fmt::Result {
+    let this_mesh = node.this;
+    writeln!(
+        config,
+        "\
+[Interface]
+# PublicKey = {}
+PrivateKey = {}",
+        this_mesh.key_pair.pubkey, this_mesh.key_pair.prikey,
+    )?;
+    if let Some(port) = this_mesh.listen_port() {
+        writeln!(
+            config,
+            "\
+ListenPort = {}",
+            port,
+        )?;
+    }
+    Ok(())
+}
+
+pub(super) fn write_peers(config: &mut String, node: &Node<'_>) -> fmt::Result {
+    for peer in node.peers.iter() {
+        writeln!(
+            config,
+            "
+[Peer]
+PublicKey = {}",
+            peer.mesh.key_pair.pubkey
+        )?;
+        if let Some(e) = peer.endpoint {
+            writeln!(
+                config,
+                "\
+Endpoint = {}",
+                e
+            )?;
+        }
+        writeln!(
+            config,
+            "\
+AllowedIPs = {}",
+            peer.allowed_ips.join(", ")
+        )?;
+        if let Some(interval) = peer.persistent_keepalive {
+            writeln!(
+                config,
+                "\
+PersistentKeepalive = {}",
+                interval
+            )?;
+        }
+    }
+    Ok(())
+}
+
+impl Renderer for WgQuick {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let mut config = String::new();
+        write_interface(&mut config, node)?;
+        writeln!(
+            config,
+            "\
+Address = {}/{}
+Address = {}/{}",
+            this_mesh.ipv4, node.ipv4_prefix, this_mesh.ipv6, node.ipv6_prefix
+        )?;
+        if node.forwarding {
+            writeln!(
+                config,
+                "\
+PostUp = sysctl -w net.ipv4.ip_forward=1
+PostUp = sysctl -w net.ipv6.conf.all.forwarding=1"
+            )?;
+        }
+        if node.masquerade {
+            let [ipv4_subnet, ipv6_subnet] = node.subnets();
+            for (hook, action) in [("PostUp", 'A'), ("PostDown", 'D')] {
+                writeln!(
+                    config,
+                    "\
+{} = iptables -t nat -{} POSTROUTING -s {} ! -o %i -j MASQUERADE
+{} = ip6tables -t nat -{} POSTROUTING -s {} ! -o %i -j MASQUERADE",
+                    hook, action, ipv4_subnet, hook, action, ipv6_subnet
+                )?;
+            }
+        }
+        write_peers(&mut config, node)?;
+        Ok(Box::new([RenderedFile {
+            name: format!("{}.conf", this_mesh.tag).into(),
+            contents: config.into(),
+            private: true,
+        }]))
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::Write as _;
+
+use crate::conf::{
+    EXIT_TABLE, Error, Node, RenderedFile, Renderer,
+    wg_quick::{write_interface, write_peers},
+};
+
+/// Renders the plain `wg setconf`/`wg syncconf` subset per node.
+///
+/// Interface addresses and routes are not part of that format, so they are
+/// written to a `<tag>.addr.sh` sidecar to be run once when the interface is
+/// created.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WgSetconf;
+
+impl Renderer for WgSetconf {
+    fn render(&self, node: &Node<'_>) -> Result<Box<[RenderedFile]>, Error> {
+        let this_mesh = node.this;
+        let mut config = String::new();
+        write_interface(&mut config, node)?;
+        write_peers(&mut config, node)?;
+
+        let mut script = String::new();
+        writeln!(
+            script,
+            "\
+#!/bin/sh
+ip address add {}/{} dev {}
+ip address add {}/{} dev {}",
+            this_mesh.ipv4,
+            node.ipv4_prefix,
+            node.interface,
+            this_mesh.ipv6,
+            node.ipv6_prefix,
+            node.interface
+        )?;
+        for route in node.routes() {
+            writeln!(script, "ip route add {} dev {}", route, node.interface)?;
+        }
+        if node.exit_node.is_some() {
+            writeln!(script, "wg set {} fwmark {}", node.interface, EXIT_TABLE)?;
+            for ip in ["ip -4", "ip -6"] {
+                writeln!(
+                    script,
+                    "\
+{} route add default dev {} table {}
+{} rule add not fwmark {} table {}
+{} rule add table main suppress_prefixlength 0",
+                    ip, node.interface, EXIT_TABLE, ip, EXIT_TABLE, EXIT_TABLE, ip
+                )?;
+            }
+        }
+        if node.forwarding {
+            writeln!(
+                script,
+                "\
+sysctl -w net.ipv4.ip_forward=1
+sysctl -w net.ipv6.conf.all.forwarding=1"
+            )?;
+        }
+        if node.masquerade {
+            let [ipv4_subnet, ipv6_subnet] = node.subnets();
+            writeln!(
+                script,
+                "\
+iptables -t nat -A POSTROUTING -s {} ! -o {} -j MASQUERADE
+ip6tables -t nat -A POSTROUTING -s {} ! -o {} -j MASQUERADE",
+                ipv4_subnet, node.interface, ipv6_subnet, node.interface
+            )?;
+        }
+
+        Ok(Box::new([
+            RenderedFile {
+                name: format!("{}.wg.conf", this_mesh.tag).into(),
+                contents: config.into(),
+                private: true,
+            },
+            RenderedFile {
+                name: format!("{}.addr.sh", this_mesh.tag).into(),
+                contents: script.into(),
+                private: false,
+            },
+        ]))
+    }
+}
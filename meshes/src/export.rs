@@ -0,0 +1,16 @@
+mod ansible;
+mod kubernetes;
+
+use crate::conf::{Conf, Error, RenderedFile};
+
+pub use ansible::Ansible;
+pub use kubernetes::Kubernetes;
+
+/// Turns a whole [`Conf`] into a set of files for a deployment tool.
+///
+/// Unlike a [`Renderer`](crate::conf::Renderer), an exporter sees every node
+/// at once, so it can emit shared files such as inventories. File names may
+/// contain directories.
+pub trait Exporter {
+    fn export(&self, conf: &Conf) -> Result<Box<[RenderedFile]>, Error>;
+}
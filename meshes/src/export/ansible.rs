@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    conf::{Conf, Error, Peer, RenderedFile},
+    export::Exporter,
+};
+
+/// Exports an Ansible inventory with one `host_vars` file per node.
+///
+/// Private keys never end up in `host_vars`. They are collected in
+/// `group_vars/<interface>/vault.yml`, which is meant to be encrypted with
+/// `ansible-vault encrypt` before it is committed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ansible;
+
+#[derive(Serialize)]
+struct Inventory<'a> {
+    all: Children<'a>,
+}
+
+#[derive(Serialize)]
+struct Children<'a> {
+    children: BTreeMap<&'a str, Hosts<'a>>,
+}
+
+#[derive(Serialize)]
+struct Hosts<'a> {
+    hosts: BTreeMap<&'a str, Host>,
+}
+
+#[derive(Serialize)]
+struct Host {}
+
+#[derive(Serialize)]
+struct HostVars<'a> {
+    wireguard_interface: &'a str,
+    wireguard_public_key: &'a str,
+    wireguard_private_key: String,
+    wireguard_ipv4: &'a str,
+    wireguard_ipv4_prefix: u8,
+    wireguard_ipv6: &'a str,
+    wireguard_ipv6_prefix: u8,
+    wireguard_endpoint: Option<&'a str>,
+    wireguard_listen_port: Option<u16>,
+    wireguard_peers: &'a [Peer<'a>],
+}
+
+#[derive(Serialize)]
+struct Vault<'a> {
+    vault_wireguard_private_keys: BTreeMap<&'a str, &'a str>,
+}
+
+impl Exporter for Ansible {
+    fn export(&self, conf: &Conf) -> Result<Box<[RenderedFile]>, Error> {
+        conf.validate()?;
+        let mut files = Vec::with_capacity(conf.meshs.len() + 2);
+
+        let inventory = Inventory {
+            all: Children {
+                children: BTreeMap::from([(
+                    &*conf.interface,
+                    Hosts {
+                        hosts: conf
+                            .meshs
+                            .iter()
+                            .map(|mesh| (&*mesh.tag, Host {}))
+                            .collect(),
+                    },
+                )]),
+            },
+        };
+        files.push(RenderedFile {
+            name: "inventory.yml".into(),
+            contents: serde_norway::to_string(&inventory)?.into(),
+            private: false,
+        });
+
+        for mesh in conf.meshs.iter() {
+            let node = conf.node(mesh);
+            let host_vars = HostVars {
+                wireguard_interface: node.interface,
+                wireguard_public_key: &mesh.key_pair.pubkey,
+                wireguard_private_key: format!(
+                    "{{{{ vault_wireguard_private_keys[{:?}] }}}}",
+                    mesh.tag
+                ),
+                wireguard_ipv4: &mesh.ipv4,
+                wireguard_ipv4_prefix: node.ipv4_prefix,
+                wireguard_ipv6: &mesh.ipv6,
+                wireguard_ipv6_prefix: node.ipv6_prefix,
+                wireguard_endpoint: mesh.endpoint.as_deref(),
+                wireguard_listen_port: mesh.listen_port().and_then(|port| port.parse().ok()),
+                wireguard_peers: &node.peers,
+            };
+            files.push(RenderedFile {
+                name: format!("host_vars/{}.yml", mesh.tag).into(),
+                contents: serde_norway::to_string(&host_vars)?.into(),
+                private: false,
+            });
+        }
+
+        let vault = Vault {
+            vault_wireguard_private_keys: conf
+                .meshs
+                .iter()
+                .map(|mesh| (&*mesh.tag, &*mesh.key_pair.prikey))
+                .collect(),
+        };
+        files.push(RenderedFile {
+            name: format!("group_vars/{}/vault.yml", conf.interface).into(),
+            contents: format!(
+                "# Encrypt this file with `ansible-vault encrypt` before committing it.\n{}",
+                serde_norway::to_string(&vault)?
+            )
+            .into(),
+            private: true,
+        });
+
+        Ok(files.into_boxed_slice())
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    conf::{Conf, Error, RenderedFile, WgQuick},
+    export::Exporter,
+};
+
+const TAG_LABEL: &str = "mesh/tag";
+
+/// Exports one Kubernetes `Secret` manifest per node, holding its wg-quick
+/// config under `<interface>.conf`. Tags have to be valid `Secret` names
+/// once prefixed, and valid label values.
+#[derive(Debug, Default, Clone)]
+pub struct Kubernetes {
+    pub namespace: Option<Box<str>>,
+    /// Prepended to the node tag to form the `Secret` name.
+    pub name_prefix: Box<str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Secret<'a> {
+    api_version: &'a str,
+    kind: &'a str,
+    metadata: Metadata<'a>,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    string_data: BTreeMap<String, &'a str>,
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace: Option<&'a str>,
+    labels: BTreeMap<&'a str, &'a str>,
+}
+
+/// Whether `s` is a DNS-1123 subdomain, as resource names have to be.
+fn is_dns_subdomain(s: &str) -> bool {
+    s.len() <= 253
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+}
+
+/// Whether `s` is a DNS-1123 label, as namespaces have to be.
+fn is_dns_label(s: &str) -> bool {
+    s.len() <= 63 && !s.contains('.') && is_dns_subdomain(s)
+}
+
+/// Whether `s` may be used as a label value.
+fn is_label_value(s: &str) -> bool {
+    s.len() <= 63
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+        && s.bytes().next().is_none_or(|b| b.is_ascii_alphanumeric())
+        && s.bytes().last().is_none_or(|b| b.is_ascii_alphanumeric())
+}
+
+fn check(name: &str, valid: fn(&str) -> bool, what: &'static str) -> Result<(), Error> {
+    if valid(name) {
+        Ok(())
+    } else {
+        Err(Error::InvalidKubernetesName {
+            name: name.into(),
+            what,
+        })
+    }
+}
+
+impl Exporter for Kubernetes {
+    fn export(&self, conf: &Conf) -> Result<Box<[RenderedFile]>, Error> {
+        conf.validate()?;
+        if let Some(namespace) = &self.namespace {
+            check(namespace, is_dns_label, "namespace")?;
+        }
+        let mut files = Vec::with_capacity(conf.meshs.len());
+        for mesh in conf.meshs.iter() {
+            let name = format!("{}{}", self.name_prefix, mesh.tag);
+            check(&name, is_dns_subdomain, "Secret name")?;
+            check(&mesh.tag, is_label_value, "label value")?;
+            let config = conf.create_single(&WgQuick, mesh)?;
+            let secret = Secret {
+                api_version: "v1",
+                kind: "Secret",
+                metadata: Metadata {
+                    name,
+                    namespace: self.namespace.as_deref(),
+                    labels: BTreeMap::from([(TAG_LABEL, &*mesh.tag)]),
+                },
+                type_: "Opaque",
+                string_data: BTreeMap::from([(
+                    format!("{}.conf", conf.interface),
+                    &*config[0].contents,
+                )]),
+            };
+            files.push(RenderedFile {
+                name: format!("{}.yaml", mesh.tag).into(),
+                contents: serde_norway::to_string(&secret)?.into(),
+                private: true,
+            });
+        }
+        Ok(files.into_boxed_slice())
+    }
+}
@@ -0,0 +1,116 @@
+mod wg_meshconf;
+mod wg_quick;
+
+use std::net::IpAddr;
+
+use crate::{
+    ip::{network, parse_cidr, to_bits},
+    mesh::{KeyError, Meshs},
+};
+
+pub use wg_meshconf::wg_meshconf;
+pub use wg_quick::wg_quick;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    CsvError(#[from] csv::Error),
+    #[error("{tag}:{line}: {message}")]
+    Syntax {
+        tag: Box<str>,
+        line: usize,
+        message: Box<str>,
+    },
+    #[error("{tag}: {source}")]
+    Key { tag: Box<str>, source: KeyError },
+    #[error("{tag}: missing {what}")]
+    Missing { tag: Box<str>, what: &'static str },
+    #[error("{tag}: invalid {what} `{value}`")]
+    Invalid {
+        tag: Box<str>,
+        what: &'static str,
+        value: Box<str>,
+    },
+    #[error("{tag}: same key pair as {other}")]
+    DuplicateKey { tag: Box<str>, other: Box<str> },
+    #[error("nothing to import")]
+    Empty,
+}
+
+/// The result of an import, along with everything that could not be carried
+/// over or looked inconsistent.
+#[derive(Debug)]
+pub struct Imported {
+    pub meshs: Meshs,
+    pub warnings: Box<[Box<str>]>,
+}
+
+/// Picks the longest prefix that is no longer than any of the declared
+/// `prefixes` and still puts all `addrs` in one network.
+fn infer_prefix(addrs: &[IpAddr], prefixes: &[u8], bits: u8) -> u8 {
+    let mut prefix = prefixes.iter().copied().min().unwrap_or(bits);
+    let addrs: Box<[_]> = addrs.iter().map(|addr| to_bits(*addr).0).collect();
+    while prefix > 0
+        && addrs
+            .iter()
+            .any(|addr| network(*addr, bits, prefix) != network(addrs[0], bits, prefix))
+    {
+        prefix -= 1;
+    }
+    prefix
+}
+
+/// Collects node addresses along with the prefixes they were declared with.
+#[derive(Default)]
+struct Addresses {
+    ipv4: Vec<IpAddr>,
+    ipv6: Vec<IpAddr>,
+    ipv4_prefixes: Vec<u8>,
+    ipv6_prefixes: Vec<u8>,
+}
+
+impl Addresses {
+    /// Takes the first IPv4 and IPv6 address of `tag`, dropping the rest.
+    fn push<'a>(
+        &mut self,
+        tag: &str,
+        addresses: impl IntoIterator<Item = &'a str>,
+        warnings: &mut Vec<Box<str>>,
+    ) -> Result<(IpAddr, IpAddr), Error> {
+        let (mut ipv4, mut ipv6) = (None, None);
+        for address in addresses {
+            let (addr, prefix) = parse_cidr(address).ok_or_else(|| Error::Invalid {
+                tag: tag.into(),
+                what: "Address",
+                value: address.into(),
+            })?;
+            let (slot, prefixes) = match addr {
+                IpAddr::V4(_) => (&mut ipv4, &mut self.ipv4_prefixes),
+                IpAddr::V6(_) => (&mut ipv6, &mut self.ipv6_prefixes),
+            };
+            if slot.is_some() {
+                warnings.push(format!("{}: extra Address `{}` was dropped", tag, address).into());
+                continue;
+            }
+            *slot = Some(addr);
+            prefixes.extend(prefix);
+        }
+        let missing = |what| Error::Missing {
+            tag: tag.into(),
+            what,
+        };
+        let ipv4 = ipv4.ok_or_else(|| missing("IPv4 Address"))?;
+        let ipv6 = ipv6.ok_or_else(|| missing("IPv6 Address"))?;
+        self.ipv4.push(ipv4);
+        self.ipv6.push(ipv6);
+        Ok((ipv4, ipv6))
+    }
+
+    /// The IPv4 and IPv6 prefixes of the imported mesh.
+    fn prefixes(&self) -> (u8, u8) {
+        (
+            infer_prefix(&self.ipv4, &self.ipv4_prefixes, 32),
+            infer_prefix(&self.ipv6, &self.ipv6_prefixes, 128),
+        )
+    }
+}
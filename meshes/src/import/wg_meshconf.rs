@@ -0,0 +1,165 @@
+use std::{collections::BTreeMap, io, net::IpAddr};
+
+use crate::{
+    import::{Addresses, Error, Imported},
+    mesh::{EndpointBoxStr, KeyPair, Mesh, Meshs, Role},
+};
+
+const NAME: &str = "Name";
+const ADDRESS: &str = "Address";
+const ENDPOINT: &str = "Endpoint";
+const LISTEN_PORT: &str = "ListenPort";
+const PERSISTENT_KEEPALIVE: &str = "PersistentKeepalive";
+const PRIVATE_KEY: &str = "PrivateKey";
+const PUBLIC_KEY: &str = "PublicKey";
+const MAPPED: [&str; 7] = [
+    NAME,
+    ADDRESS,
+    ENDPOINT,
+    LISTEN_PORT,
+    PERSISTENT_KEEPALIVE,
+    PRIVATE_KEY,
+    PUBLIC_KEY,
+];
+
+/// Reads a wg-meshconf `database.csv` into a [`Meshs`].
+///
+/// Columns without a counterpart in [`Mesh`], such as `DNS` or `PostUp`,
+/// are reported in [`Imported::warnings`] whenever they hold a value.
+pub fn wg_meshconf(csv: impl io::Read) -> Result<Imported, Error> {
+    let mut reader = csv::Reader::from_reader(csv);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let name_column = column(NAME).ok_or(Error::Missing {
+        tag: "database".into(),
+        what: "`Name` column",
+    })?;
+    let unmapped: Box<[_]> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| !MAPPED.iter().any(|m| m.eq_ignore_ascii_case(h)))
+        .collect();
+    let mut dropped: Vec<Vec<Box<str>>> = vec![Vec::new(); unmapped.len()];
+
+    let mut warnings = Vec::new();
+    let mut addresses = Addresses::default();
+    let mut meshs = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |name: &str| {
+            column(name)
+                .and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let tag = record.get(name_column).unwrap_or_default().trim();
+        let missing = |what| Error::Missing {
+            tag: tag.into(),
+            what,
+        };
+
+        let prikey = field(PRIVATE_KEY).ok_or_else(|| missing("PrivateKey"))?;
+        let key_pair = KeyPair::from_prikey(prikey).map_err(|source| Error::Key {
+            tag: tag.into(),
+            source,
+        })?;
+        if let Some(pubkey) = field(PUBLIC_KEY) {
+            KeyPair {
+                pubkey: pubkey.into(),
+                prikey: prikey.into(),
+            }
+            .validate()
+            .map_err(|source| Error::Key {
+                tag: tag.into(),
+                source,
+            })?;
+        }
+
+        let (ipv4, ipv6) = addresses.push(
+            tag,
+            field(ADDRESS)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty()),
+            &mut warnings,
+        )?;
+
+        let endpoint = match (field(ENDPOINT), field(LISTEN_PORT)) {
+            (Some(host), Some(port)) if host.parse::<IpAddr>().is_ok_and(|ip| ip.is_ipv6()) => {
+                Some(format!("[{}]:{}", host, port))
+            }
+            (Some(host), Some(port)) => Some(format!("{}:{}", host, port)),
+            (Some(endpoint), None) => Some(endpoint.to_string()),
+            (None, Some(port)) => {
+                warnings.push(
+                    format!(
+                        "{}: ListenPort `{}` was dropped, it has no Endpoint",
+                        tag, port
+                    )
+                    .into(),
+                );
+                None
+            }
+            (None, None) => None,
+        };
+        if let Some(endpoint) = &endpoint {
+            EndpointBoxStr::validate(endpoint).map_err(|_| Error::Invalid {
+                tag: tag.into(),
+                what: "Endpoint",
+                value: endpoint.as_str().into(),
+            })?;
+        }
+
+        let persistent_keepalive = field(PERSISTENT_KEEPALIVE)
+            .map(|value| {
+                value.parse::<u16>().map_err(|_| Error::Invalid {
+                    tag: tag.into(),
+                    what: "PersistentKeepalive",
+                    value: value.into(),
+                })
+            })
+            .transpose()?;
+
+        for ((i, _), dropped) in unmapped.iter().zip(dropped.iter_mut()) {
+            if record.get(*i).is_some_and(|v| !v.trim().is_empty()) {
+                dropped.push(tag.into());
+            }
+        }
+
+        meshs.push(Mesh {
+            tag: tag.into(),
+            key_pair,
+            ipv4: ipv4.to_string().into(),
+            ipv6: ipv6.to_string().into(),
+            endpoint: endpoint.map(Into::into),
+            location: None,
+            endpoints: BTreeMap::new(),
+            role: Role::Hub,
+            routes: Box::default(),
+            exit_node: None,
+            persistent_keepalive,
+        });
+    }
+    if meshs.is_empty() {
+        return Err(Error::Empty);
+    }
+    for ((_, column), tags) in unmapped.iter().zip(dropped) {
+        if !tags.is_empty() {
+            warnings.push(
+                format!(
+                    "column `{}` is not supported, its values were dropped for: {}",
+                    column,
+                    tags.join(", ")
+                )
+                .into(),
+            );
+        }
+    }
+
+    let (ipv4_prefix, ipv6_prefix) = addresses.prefixes();
+    Ok(Imported {
+        meshs: Meshs::new(meshs, ipv4_prefix, ipv6_prefix),
+        warnings: warnings.into_boxed_slice(),
+    })
+}
@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    conf::DEFAULT_KEEPALIVE,
+    import::{Addresses, Error, Imported},
+    ip::{contains, covers, overlaps, parse_cidr, subnet, to_bits},
+    mesh::{EndpointBoxStr, KeyPair, Mesh, Meshs, Role},
+};
+
+#[derive(Default)]
+struct Interface {
+    prikey: Option<Box<str>>,
+    addresses: Vec<Box<str>>,
+    listen_port: Option<Box<str>>,
+}
+
+struct Peer {
+    line: usize,
+    pubkey: Option<Box<str>>,
+    endpoint: Option<Box<str>>,
+    allowed_ips: Vec<Box<str>>,
+    persistent_keepalive: Option<u16>,
+}
+
+struct File<'a> {
+    tag: &'a str,
+    interface: Interface,
+    peers: Vec<Peer>,
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = Box<str>> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(Box::from)
+}
+
+fn parse<'a>(
+    tag: &'a str,
+    contents: &str,
+    warnings: &mut Vec<Box<str>>,
+) -> Result<File<'a>, Error> {
+    enum Section {
+        None,
+        Interface,
+        Peer,
+    }
+    let syntax = |line: usize, message: &str| Error::Syntax {
+        tag: tag.into(),
+        line,
+        message: message.into(),
+    };
+    let mut file = File {
+        tag,
+        interface: Interface::default(),
+        peers: Vec::new(),
+    };
+    let mut section = Section::None;
+    let mut ignored: Vec<Box<str>> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = match name.trim().to_ascii_lowercase().as_str() {
+                "interface" => Section::Interface,
+                "peer" => {
+                    file.peers.push(Peer {
+                        line: line_no,
+                        pubkey: None,
+                        endpoint: None,
+                        allowed_ips: Vec::new(),
+                        persistent_keepalive: None,
+                    });
+                    Section::Peer
+                }
+                _ => return Err(syntax(line_no, "unknown section")),
+            };
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(syntax(line_no, "expected `Key = Value`"));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match (&section, key.to_ascii_lowercase().as_str()) {
+            (Section::None, _) => return Err(syntax(line_no, "key outside of a section")),
+            (Section::Interface, "privatekey") => file.interface.prikey = Some(value.into()),
+            (Section::Interface, "address") => file.interface.addresses.extend(split_list(value)),
+            (Section::Interface, "listenport") => file.interface.listen_port = Some(value.into()),
+            (Section::Peer, "publickey") => {
+                file.peers.last_mut().unwrap().pubkey = Some(value.into())
+            }
+            (Section::Peer, "endpoint") => {
+                file.peers.last_mut().unwrap().endpoint = Some(value.into())
+            }
+            (Section::Peer, "allowedips") => file
+                .peers
+                .last_mut()
+                .unwrap()
+                .allowed_ips
+                .extend(split_list(value)),
+            (Section::Peer, "persistentkeepalive") => {
+                let interval = match value {
+                    "off" => 0,
+                    _ => value
+                        .parse()
+                        .map_err(|_| syntax(line_no, "invalid PersistentKeepalive"))?,
+                };
+                file.peers.last_mut().unwrap().persistent_keepalive = Some(interval)
+            }
+            _ => {
+                if !ignored.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+                    ignored.push(key.into());
+                }
+            }
+        }
+    }
+    for key in ignored {
+        warnings.push(format!("{}: `{}` is not supported and was dropped", tag, key).into());
+    }
+    Ok(file)
+}
+
+/// Rebuilds a [`Meshs`] from wg-quick configs, given as `(tag, contents)`.
+///
+/// Key pairs are derived from each `PrivateKey`, addresses come from
+/// `Address` and endpoints from the `[Peer]` sections of the other configs.
+/// AllowedIPs outside of the mesh subnet become the peer's `routes`, or make
+/// it the exit node for a default route.
+pub fn wg_quick<'a>(
+    configs: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Imported, Error> {
+    let mut warnings = Vec::new();
+    let files = configs
+        .into_iter()
+        .map(|(tag, contents)| parse(tag, contents, &mut warnings))
+        .collect::<Result<Vec<_>, _>>()?;
+    if files.is_empty() {
+        return Err(Error::Empty);
+    }
+
+    let mut key_pairs = Vec::with_capacity(files.len());
+    let mut addrs = Vec::with_capacity(files.len());
+    let mut addresses = Addresses::default();
+    for file in files.iter() {
+        let tag = file.tag;
+        let prikey = file.interface.prikey.clone().ok_or(Error::Missing {
+            tag: tag.into(),
+            what: "PrivateKey",
+        })?;
+        key_pairs.push(KeyPair::from_prikey(prikey).map_err(|source| Error::Key {
+            tag: tag.into(),
+            source,
+        })?);
+        addrs.push(addresses.push(
+            tag,
+            file.interface.addresses.iter().map(|a| &**a),
+            &mut warnings,
+        )?);
+    }
+    let mut by_pubkey: HashMap<&str, usize> = HashMap::with_capacity(files.len());
+    for (i, key_pair) in key_pairs.iter().enumerate() {
+        if let Some(j) = by_pubkey.insert(&key_pair.pubkey, i) {
+            return Err(Error::DuplicateKey {
+                tag: files[i].tag.into(),
+                other: files[j].tag.into(),
+            });
+        }
+    }
+
+    let (ipv4_prefix, ipv6_prefix) = addresses.prefixes();
+    let subnets = [
+        subnet(addrs[0].0, ipv4_prefix),
+        subnet(addrs[0].1, ipv6_prefix),
+    ];
+
+    let mut endpoints: Vec<Option<Box<str>>> = vec![None; files.len()];
+    let mut routes: Vec<Vec<Box<str>>> = vec![Vec::new(); files.len()];
+    let mut exit_nodes: Vec<Option<usize>> = vec![None; files.len()];
+    let mut keepalives: Vec<Option<u16>> = vec![None; files.len()];
+    let mut peerings = vec![vec![false; files.len()]; files.len()];
+    for (i, file) in files.iter().enumerate() {
+        for peer in file.peers.iter() {
+            let Some(pubkey) = &peer.pubkey else {
+                return Err(Error::Syntax {
+                    tag: file.tag.into(),
+                    line: peer.line,
+                    message: "peer without PublicKey".into(),
+                });
+            };
+            let Some(&j) = by_pubkey.get(&**pubkey) else {
+                warnings.push(
+                    format!(
+                        "{}: peer `{}` does not belong to any imported config",
+                        file.tag, pubkey
+                    )
+                    .into(),
+                );
+                continue;
+            };
+            if i == j {
+                warnings.push(format!("{}: peers with itself", file.tag).into());
+                continue;
+            }
+            peerings[i][j] = true;
+            if let Some(interval) = peer.persistent_keepalive {
+                match keepalives[i] {
+                    None => keepalives[i] = Some(interval),
+                    Some(known) if known != interval => warnings.push(
+                        format!(
+                            "{}: PersistentKeepalive {} of `{}` differs from {}, keeping the latter",
+                            file.tag, interval, files[j].tag, known
+                        )
+                        .into(),
+                    ),
+                    Some(_) => {}
+                }
+            }
+            let (ipv4, ipv6) = addrs[j];
+            if !peer.allowed_ips.iter().any(|ip| covers(ip, ipv4))
+                || !peer.allowed_ips.iter().any(|ip| covers(ip, ipv6))
+            {
+                warnings.push(
+                    format!(
+                        "{}: AllowedIPs of peer `{}` do not cover {} and {}",
+                        file.tag, files[j].tag, ipv4, ipv6
+                    )
+                    .into(),
+                );
+            }
+            for ip in peer.allowed_ips.iter() {
+                let Some((addr, prefix)) = parse_cidr(ip) else {
+                    return Err(Error::Invalid {
+                        tag: file.tag.into(),
+                        what: "AllowedIPs",
+                        value: ip.clone(),
+                    });
+                };
+                let (_, bits) = to_bits(addr);
+                let ip: Box<str> = format!("{}/{}", addr, prefix.unwrap_or(bits)).into();
+                if subnets.iter().any(|subnet| contains(subnet, &ip)) {
+                    continue;
+                }
+                if prefix == Some(0) {
+                    // A default route makes the peer this node's exit node
+                    match exit_nodes[i] {
+                        None => exit_nodes[i] = Some(j),
+                        Some(k) if k != j => warnings.push(
+                            format!(
+                                "{}: exit node `{}` differs from `{}`, keeping the latter",
+                                file.tag, files[j].tag, files[k].tag
+                            )
+                            .into(),
+                        ),
+                        Some(_) => {}
+                    }
+                } else if subnets.iter().any(|subnet| overlaps(subnet, &ip)) {
+                    warnings.push(
+                        format!(
+                            "{}: AllowedIPs `{}` of peer `{}` overlaps the mesh subnet and was dropped",
+                            file.tag, ip, files[j].tag
+                        )
+                        .into(),
+                    );
+                } else if let Some(k) = (0..files.len())
+                    .find(|&k| k != j && routes[k].iter().any(|route| overlaps(route, &ip)))
+                {
+                    warnings.push(
+                        format!(
+                            "{}: AllowedIPs `{}` of peer `{}` is already routed to `{}` and was dropped",
+                            file.tag, ip, files[j].tag, files[k].tag
+                        )
+                        .into(),
+                    );
+                } else if !routes[j].contains(&ip) {
+                    routes[j].push(ip);
+                }
+            }
+            if let Some(endpoint) = &peer.endpoint {
+                EndpointBoxStr::validate(endpoint).map_err(|_| Error::Invalid {
+                    tag: file.tag.into(),
+                    what: "Endpoint",
+                    value: endpoint.clone(),
+                })?;
+                match &endpoints[j] {
+                    None => endpoints[j] = Some(endpoint.clone()),
+                    Some(known) if known != endpoint => warnings.push(
+                        format!(
+                            "{}: endpoint `{}` of `{}` differs from `{}`, keeping the latter",
+                            file.tag, endpoint, files[j].tag, known
+                        )
+                        .into(),
+                    ),
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+    for (file, endpoint) in files.iter().zip(endpoints.iter()) {
+        let Some(listen_port) = &file.interface.listen_port else {
+            continue;
+        };
+        match endpoint.as_ref().and_then(|e| e.rsplit_once(':')) {
+            None => warnings.push(
+                format!(
+                    "{}: ListenPort {} was dropped, no peer has an Endpoint for it",
+                    file.tag, listen_port
+                )
+                .into(),
+            ),
+            Some((_, port)) if port != &**listen_port => warnings.push(
+                format!(
+                    "{}: ListenPort {} differs from the port {} peers connect to",
+                    file.tag, listen_port, port
+                )
+                .into(),
+            ),
+            Some(_) => {}
+        }
+    }
+    for i in 0..files.len() {
+        for j in i + 1..files.len() {
+            let (a, b) = (files[i].tag, files[j].tag);
+            match (peerings[i][j], peerings[j][i]) {
+                (true, true) => {}
+                (true, false) => warnings.push(asymmetric(a, b)),
+                (false, true) => warnings.push(asymmetric(b, a)),
+                (false, false) => warnings
+                    .push(format!("{} and {} do not peer, they will once converted", a, b).into()),
+            }
+        }
+    }
+
+    let meshs: Box<[_]> = files
+        .iter()
+        .zip(key_pairs)
+        .enumerate()
+        .map(|(i, (file, key_pair))| {
+            let (ipv4, ipv6) = addrs[i];
+            let endpoint = endpoints[i].clone();
+            // Nodes without an endpoint get the default interval anyway
+            let persistent_keepalive = keepalives[i]
+                .filter(|&interval| endpoint.is_some() || interval != DEFAULT_KEEPALIVE);
+            Mesh {
+                tag: file.tag.into(),
+                key_pair,
+                ipv4: ipv4.to_string().into(),
+                ipv6: ipv6.to_string().into(),
+                endpoint: endpoint.map(Into::into),
+                location: None,
+                endpoints: BTreeMap::new(),
+                role: Role::Hub,
+                routes: routes[i].iter().map(Into::into).collect(),
+                exit_node: exit_nodes[i].map(|k| files[k].tag.into()),
+                persistent_keepalive,
+            }
+        })
+        .collect();
+    Ok(Imported {
+        meshs: Meshs::new(meshs, ipv4_prefix, ipv6_prefix),
+        warnings: warnings.into_boxed_slice(),
+    })
+}
+
+fn asymmetric(from: &str, to: &str) -> Box<str> {
+    format!("{} peers with {}, but not the other way around", from, to).into()
+}
@@ -0,0 +1,87 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Splits `addr[/prefix]`, rejecting prefixes longer than the address.
+pub(crate) fn parse_cidr(s: &str) -> Option<(IpAddr, Option<u8>)> {
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().ok()?)),
+        None => (s, None),
+    };
+    let addr: IpAddr = addr.parse().ok()?;
+    let bits = if addr.is_ipv4() { 32 } else { 128 };
+    match prefix {
+        Some(prefix) if prefix > bits => None,
+        _ => Some((addr, prefix)),
+    }
+}
+
+pub(crate) fn to_bits(addr: IpAddr) -> (u128, u8) {
+    match addr {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    }
+}
+
+pub(crate) fn network(addr: u128, bits: u8, prefix: u8) -> u128 {
+    let host_bits = bits - prefix;
+    if host_bits >= 128 {
+        0
+    } else {
+        addr >> host_bits << host_bits
+    }
+}
+
+/// Whether the network `cidr` contains `addr`.
+pub(crate) fn covers(cidr: &str, addr: IpAddr) -> bool {
+    let Some((net, prefix)) = parse_cidr(cidr) else {
+        return false;
+    };
+    let ((net, bits), (addr, addr_bits)) = (to_bits(net), to_bits(addr));
+    let prefix = prefix.unwrap_or(bits);
+    bits == addr_bits && network(net, bits, prefix) == network(addr, bits, prefix)
+}
+
+pub(crate) fn from_bits(addr: u128, bits: u8) -> IpAddr {
+    if bits == 32 {
+        IpAddr::V4(Ipv4Addr::from(addr as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(addr))
+    }
+}
+
+/// The network `addr` belongs to, as `network/prefix`.
+pub(crate) fn subnet(addr: IpAddr, prefix: u8) -> Box<str> {
+    let (addr, bits) = to_bits(addr);
+    format!(
+        "{}/{}",
+        from_bits(network(addr, bits, prefix), bits),
+        prefix
+    )
+    .into()
+}
+
+/// Splits `addr[/prefix]` into its bits, width and prefix.
+fn network_of(cidr: &str) -> Option<(u128, u8, u8)> {
+    let (addr, prefix) = parse_cidr(cidr)?;
+    let (addr, bits) = to_bits(addr);
+    Some((addr, bits, prefix.unwrap_or(bits)))
+}
+
+/// Whether the networks `a` and `b` share any address.
+pub(crate) fn overlaps(a: &str, b: &str) -> bool {
+    let (Some((a, a_bits, a_prefix)), Some((b, b_bits, b_prefix))) = (network_of(a), network_of(b))
+    else {
+        return false;
+    };
+    let prefix = a_prefix.min(b_prefix);
+    a_bits == b_bits && network(a, a_bits, prefix) == network(b, b_bits, prefix)
+}
+
+/// Whether the network `outer` contains all of `inner`.
+pub(crate) fn contains(outer: &str, inner: &str) -> bool {
+    match (network_of(outer), network_of(inner)) {
+        (Some((_, _, outer_prefix)), Some((_, _, inner_prefix))) => {
+            outer_prefix <= inner_prefix && overlaps(outer, inner)
+        }
+        _ => false,
+    }
+}
@@ -1,3 +1,7 @@
 pub mod conf;
+pub mod export;
+pub mod import;
+mod ip;
 pub mod mesh;
+pub mod migrate;
 mod test;
@@ -1,4 +1,6 @@
 use std::{
+    borrow::Cow,
+    collections::BTreeMap,
     fmt,
     net::{Ipv4Addr, Ipv6Addr},
     ops::{Deref, DerefMut},
@@ -6,12 +8,15 @@ use std::{
 };
 
 use base64::{Engine as _, engine::general_purpose::STANDARD};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{
     Deserialize, Deserializer, Serialize,
-    de::{self, MapAccess, Visitor},
+    de::{self, DeserializeOwned, MapAccess, Visitor},
 };
 use x25519_dalek::{PublicKey, StaticSecret};
 
+use crate::ip::parse_cidr;
+
 macro_rules! create_boxed_struct {
     ($($struct_name:ident),+) => {
         $(
@@ -84,7 +89,94 @@ macro_rules! impl_json {
     };
 }
 
-create_boxed_struct!(Ipv4BoxStr, Ipv6BoxStr, EndpointBoxStr);
+pub trait ToYaml: Serialize {
+    fn to_yaml(&self) -> Result<Box<str>, serde_norway::Error>;
+}
+
+pub trait FromYaml: Sized + DeserializeOwned {
+    fn from_yaml(v: impl AsRef<str>) -> Result<Self, serde_norway::Error>;
+}
+
+macro_rules! impl_yaml {
+    ($($type:ident),+) => {
+        $(
+            impl ToYaml for $type {
+                fn to_yaml(&self) -> Result<Box<str>, serde_norway::Error> {
+                    serde_norway::to_string(self).map(|s| s.into_boxed_str())
+                }
+            }
+            impl FromYaml for $type {
+                fn from_yaml(v: impl AsRef<str>) -> Result<Self, serde_norway::Error> {
+                    serde_norway::from_str(v.as_ref())
+                }
+            }
+        )+
+    };
+}
+
+pub trait ToToml: Serialize {
+    fn to_toml(&self) -> Result<Box<str>, toml::ser::Error>;
+}
+
+pub trait FromToml: Sized + DeserializeOwned {
+    fn from_toml(v: impl AsRef<str>) -> Result<Self, toml::de::Error>;
+}
+
+macro_rules! impl_toml {
+    ($($type:ident),+) => {
+        $(
+            impl ToToml for $type {
+                fn to_toml(&self) -> Result<Box<str>, toml::ser::Error> {
+                    toml::to_string_pretty(self).map(|s| s.into_boxed_str())
+                }
+            }
+            impl FromToml for $type {
+                fn from_toml(v: impl AsRef<str>) -> Result<Self, toml::de::Error> {
+                    toml::from_str(v.as_ref())
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_string_schema {
+    ($(($type:ty, $schema:tt)),* $(,)?) => {
+        $(
+            impl JsonSchema for $type {
+                fn schema_name() -> Cow<'static, str> {
+                    stringify!($type).into()
+                }
+                fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                    json_schema!($schema)
+                }
+            }
+        )*
+    };
+}
+
+create_boxed_struct!(Ipv4BoxStr, Ipv6BoxStr, EndpointBoxStr, CidrBoxStr);
+
+/// Base64 of exactly 32 bytes, as accepted by `decode_key`.
+const KEY_PATTERN: &str = "^[A-Za-z0-9+/]{42}[AEIMQUYcgkosw048]=$";
+
+/// Mirrors [`EndpointBoxStr::validate`]: a colon without brackets, or a colon
+/// after the last `]` when both brackets are present.
+const ENDPOINT_PATTERN: &str = concat!(
+    r"^(?:[^\[\]]*:[^\[\]]*",
+    r"|.*\[.*\][^\]]*:[^\]]*",
+    r"|.*\][^\]]*(?:\[[^\]]*:|:[^\]]*\[)[^\]]*)$",
+);
+
+impl_string_schema!(
+    (Ipv4BoxStr, { "type": "string", "format": "ipv4" }),
+    (Ipv6BoxStr, { "type": "string", "format": "ipv6" }),
+    (EndpointBoxStr, { "type": "string", "pattern": ENDPOINT_PATTERN }),
+    (CidrBoxStr, {
+        "type": "string",
+        "description": "IPv4 or IPv6 network as `address/prefix`",
+        "pattern": "^[0-9A-Fa-f:.]+/[0-9]{1,3}$",
+    }),
+);
 
 #[derive(Serialize, Debug, Default, PartialEq, Eq, Clone)]
 pub struct KeyPair {
@@ -92,6 +184,62 @@ pub struct KeyPair {
     pub prikey: Box<str>,
 }
 
+/// The schema cannot tell whether `pubkey` belongs to `prikey`.
+impl JsonSchema for KeyPair {
+    fn schema_name() -> Cow<'static, str> {
+        "KeyPair".into()
+    }
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "pubkey": { "type": "string", "pattern": KEY_PATTERN },
+                "prikey": { "type": "string", "pattern": KEY_PATTERN },
+            },
+            "required": ["pubkey", "prikey"],
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("Failed to decode {0}: {1}")]
+    Decode(&'static str, base64::DecodeError),
+    #[error("Invalid {0} length: {1} bytes (expected 32)")]
+    Length(&'static str, usize),
+    #[error("Key pair mismatch")]
+    Mismatch,
+}
+
+fn decode_key(name: &'static str, key: &str) -> Result<[u8; 32], KeyError> {
+    let bytes = STANDARD
+        .decode(key)
+        .map_err(|e| KeyError::Decode(name, e))?;
+    <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| KeyError::Length(name, bytes.len()))
+}
+
+impl KeyPair {
+    /// Builds a key pair by deriving the public key from `prikey`.
+    pub fn from_prikey(prikey: impl Into<Box<str>>) -> Result<Self, KeyError> {
+        let prikey = prikey.into();
+        let pubkey = PublicKey::from(&StaticSecret::from(decode_key("prikey", &prikey)?));
+        Ok(KeyPair {
+            pubkey: STANDARD.encode(pubkey).into(),
+            prikey,
+        })
+    }
+
+    /// Checks that both keys decode and that `pubkey` belongs to `prikey`.
+    pub fn validate(&self) -> Result<(), KeyError> {
+        let prikey = decode_key("prikey", &self.prikey)?;
+        let pubkey = decode_key("pubkey", &self.pubkey)?;
+        if *PublicKey::from(&StaticSecret::from(prikey)).as_bytes() != pubkey {
+            return Err(KeyError::Mismatch);
+        }
+        Ok(())
+    }
+}
+
 impl<'de> Deserialize<'de> for KeyPair {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -109,11 +257,11 @@ impl<'de> Deserialize<'de> for KeyPair {
             where
                 A: MapAccess<'de>,
             {
-                let mut pubkey_str: Option<&str> = None;
-                let mut prikey_str: Option<&str> = None;
+                let mut pubkey_str: Option<Box<str>> = None;
+                let mut prikey_str: Option<Box<str>> = None;
 
-                while let Some(key) = map.next_key()? {
-                    match key {
+                while let Some(key) = map.next_key::<Box<str>>()? {
+                    match &*key {
                         FIELD_PUBKEY => {
                             if pubkey_str.is_some() {
                                 return Err(de::Error::duplicate_field(FIELD_PUBKEY));
@@ -136,23 +284,12 @@ impl<'de> Deserialize<'de> for KeyPair {
                     pubkey_str.ok_or_else(|| de::Error::missing_field(FIELD_PUBKEY))?;
                 let prikey_str =
                     prikey_str.ok_or_else(|| de::Error::missing_field(FIELD_PRIKEY))?;
-                let prikey = STANDARD
-                    .decode(prikey_str)
-                    .map_err(|e| de::Error::custom(format!("Failed to decode prikey: {}", e)))?;
-                let pubkey = STANDARD
-                    .decode(pubkey_str)
-                    .map_err(|e| de::Error::custom(format!("Failed to decode pubkey: {}", e)))?;
-                let ppubkey = PublicKey::from(&StaticSecret::from(
-                    <[u8; 32]>::try_from(prikey.as_slice()).map_err(de::Error::custom)?,
-                ));
-                if *ppubkey.as_bytes() != *pubkey {
-                    return Err(de::Error::custom("Key pair mismatch"));
-                }
-
-                Ok(KeyPair {
-                    pubkey: pubkey_str.into(),
-                    prikey: prikey_str.into(),
-                })
+                let key_pair = KeyPair {
+                    pubkey: pubkey_str,
+                    prikey: prikey_str,
+                };
+                key_pair.validate().map_err(de::Error::custom)?;
+                Ok(key_pair)
             }
         }
         deserializer.deserialize_map(KeyPairVisitor)
@@ -168,38 +305,84 @@ pub enum EndpointParseError {
     MissingPort,
 }
 
+impl EndpointBoxStr {
+    /// Checks that `s` is a `host:port` pair, with IPv6 hosts in brackets.
+    pub fn validate(s: &str) -> Result<(), EndpointParseError> {
+        if s.contains('[') && s.contains(']') {
+            let i = s.rfind(']').unwrap();
+            if s[i..].rfind(':').is_none() {
+                return Err(EndpointParseError::MissingPort);
+            }
+        } else if s.contains('[') || s.contains(']') {
+            return Err(EndpointParseError::InvalidSyntax);
+        } else if s.rfind(':').is_none() {
+            return Err(EndpointParseError::MissingPort);
+        }
+        Ok(())
+    }
+
+    /// The host part of the endpoint, without the brackets around IPv6 addresses.
+    pub fn host(&self) -> &str {
+        let (host, _) = self.rsplit_once(':').unwrap();
+        host.strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host)
+    }
+
+    /// The port part of the endpoint.
+    pub fn port(&self) -> &str {
+        self.rsplit(':').next().unwrap()
+    }
+
+    /// The same host on another port.
+    pub fn with_port(&self, port: u16) -> EndpointBoxStr {
+        let (host, _) = self.rsplit_once(':').unwrap();
+        format!("{}:{}", host, port).into()
+    }
+}
+
 impl<'de> Deserialize<'de> for EndpointBoxStr {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
         let s: Box<str> = Deserialize::deserialize(deserializer)?;
-
-        fn validate(s: &str) -> Result<(), EndpointParseError> {
-            if s.contains('[') && s.contains(']') {
-                let i = s.rfind(']').unwrap();
-                if s[i..].rfind(':').is_none() {
-                    return Err(EndpointParseError::MissingPort);
-                }
-            } else if s.contains('[') || s.contains(']') {
-                return Err(EndpointParseError::InvalidSyntax);
-            } else if s.rfind(':').is_none() {
-                return Err(EndpointParseError::MissingPort);
-            }
-            Ok(())
-        }
-
-        validate(&s).map_err(de::Error::custom)?;
+        EndpointBoxStr::validate(&s).map_err(de::Error::custom)?;
         Ok(EndpointBoxStr(s))
     }
 }
 
+fn parse_network(s: &str) -> Result<(), &'static str> {
+    match parse_cidr(s) {
+        Some((_, Some(_))) => Ok(()),
+        _ => Err("Invalid network, expected `address/prefix`"),
+    }
+}
+
 impl_ip_deserialize!(
     (Ipv4BoxStr, Ipv4Addr::from_str),
-    (Ipv6BoxStr, Ipv6Addr::from_str)
+    (Ipv6BoxStr, Ipv6Addr::from_str),
+    (CidrBoxStr, parse_network)
 );
 
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+/// How a node takes part in the mesh.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Peers with every other node.
+    #[default]
+    Hub,
+    /// Peers only with hubs and reaches the rest of the mesh through them.
+    Spoke,
+}
+
+impl Role {
+    fn is_hub(&self) -> bool {
+        *self == Role::Hub
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq, Eq, Clone)]
 pub struct Mesh {
     pub tag: Box<str>,
     #[serde(flatten)]
@@ -207,6 +390,25 @@ pub struct Mesh {
     pub ipv4: Ipv4BoxStr,
     pub ipv6: Ipv6BoxStr,
     pub endpoint: Option<EndpointBoxStr>,
+    /// Where this node is, e.g. a LAN, picking its peers' `endpoints`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Box<str>>,
+    /// Endpoints of this node as seen from peers in a given location, used
+    /// instead of `endpoint`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub endpoints: BTreeMap<Box<str>, EndpointBoxStr>,
+    #[serde(default, skip_serializing_if = "Role::is_hub")]
+    pub role: Role,
+    /// Networks behind this node, routed to it by every peer.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub routes: Box<[CidrBoxStr]>,
+    /// Tag of the peer this node sends its internet traffic through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_node: Option<Box<str>>,
+    /// Keepalive interval in seconds towards peers with an endpoint,
+    /// overriding the mesh-wide one. `0` disables it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistent_keepalive: Option<u16>,
 }
 
 impl Mesh {
@@ -228,8 +430,33 @@ impl Mesh {
             ipv4: ipv4.into(),
             ipv6: ipv6.into(),
             endpoint,
+            location: None,
+            endpoints: BTreeMap::new(),
+            role: Role::default(),
+            routes: Box::default(),
+            exit_node: None,
+            persistent_keepalive: None,
         }
     }
+
+    /// The endpoint `viewer` reaches this node at: the one for its location,
+    /// else the public one.
+    pub fn endpoint_for(&self, viewer: &Mesh) -> Option<&EndpointBoxStr> {
+        viewer
+            .location
+            .as_ref()
+            .and_then(|location| self.endpoints.get(location))
+            .or(self.endpoint.as_ref())
+    }
+
+    /// The port this node listens on, taken from any of its endpoints.
+    pub fn listen_port(&self) -> Option<&str> {
+        self.endpoint
+            .iter()
+            .chain(self.endpoints.values())
+            .next()
+            .map(EndpointBoxStr::port)
+    }
 }
 
 fn deserialize_with_max<'de, const MAX: u8, D>(deserializer: D) -> Result<u8, D::Error>
@@ -247,23 +474,171 @@ where
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+/// Version of the config schema, see [`crate::migrate`] for older ones.
+pub const VERSION: u32 = 1;
+
+fn deserialize_version<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u32::deserialize(deserializer)?;
+    if value != VERSION {
+        Err(de::Error::custom(format!(
+            "Unsupported version: {} (expected {}), migrate the config first",
+            value, VERSION
+        )))
+    } else {
+        Ok(value)
+    }
+}
+
+fn version_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({ "type": "integer", "const": VERSION })
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// Allows or denies peering between two selectors, each either a tag or a
+/// `@group`. Rules are symmetric.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, Clone)]
+pub struct Rule {
+    pub action: Action,
+    pub between: [Box<str>; 2],
+}
+
+/// Which pairs of nodes may peer. The first matching rule wins, pairs no
+/// rule matches fall back to `default`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq, Eq, Clone)]
+pub struct Policy {
+    #[serde(default)]
+    pub default: Action,
+    /// Named sets of tags, referenced as `@name` in rules.
+    #[serde(default)]
+    pub groups: BTreeMap<Box<str>, Box<[Box<str>]>>,
+    #[serde(default)]
+    pub rules: Box<[Rule]>,
+}
+
+impl Policy {
+    /// Whether `selector` matches the node tagged `tag`.
+    pub fn matches(&self, selector: &str, tag: &str) -> bool {
+        match selector.strip_prefix('@') {
+            Some(group) => self
+                .groups
+                .get(group)
+                .is_some_and(|tags| tags.iter().any(|t| **t == *tag)),
+            None => selector == tag,
+        }
+    }
+
+    pub fn allows(&self, a: &str, b: &str) -> bool {
+        self.rules
+            .iter()
+            .find(|rule| {
+                let [x, y] = &rule.between;
+                (self.matches(x, a) && self.matches(y, b))
+                    || (self.matches(x, b) && self.matches(y, a))
+            })
+            .map_or(self.default, |rule| rule.action)
+            == Action::Allow
+    }
+}
+
+/// A host taking part in a [`Network`], on the host's own addresses and
+/// routes unless given here.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, Clone)]
+pub struct Member {
+    pub tag: Box<str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<Ipv4BoxStr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<Ipv6BoxStr>,
+    /// Networks behind the host routed through this network, `[]` for none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Box<[CidrBoxStr]>>,
+}
+
+/// A mesh between some of the hosts in `meshs`, on an interface of its own.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, Clone)]
+pub struct Network {
+    /// Name of the network, also its WireGuard interface on every member.
+    pub name: Box<str>,
+    /// Port every member listens on, replacing the port of its endpoints.
+    pub listen_port: u16,
+    #[serde(deserialize_with = "deserialize_with_max::<32, _>")]
+    #[schemars(range(max = 32))]
+    pub ipv4_prefix: u8,
+    #[serde(deserialize_with = "deserialize_with_max::<128, _>")]
+    #[schemars(range(max = 128))]
+    pub ipv6_prefix: u8,
+    pub members: Box<[Member]>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, Clone)]
 pub struct Meshs {
+    #[serde(deserialize_with = "deserialize_version")]
+    #[schemars(schema_with = "version_schema")]
+    pub version: u32,
     pub meshs: Box<[Mesh]>,
     #[serde(deserialize_with = "deserialize_with_max::<32, _>")]
+    #[schemars(range(max = 32))]
     pub ipv4_prefix: u8,
     #[serde(deserialize_with = "deserialize_with_max::<128, _>")]
+    #[schemars(range(max = 128))]
     pub ipv6_prefix: u8,
+    /// Tag of the node relaying between nodes that cannot reach each other,
+    /// the first node with an endpoint if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<Box<str>>,
+    /// Keepalive interval in seconds of nodes without an endpoint,
+    /// `DEFAULT_KEEPALIVE` if unset. `0` disables it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistent_keepalive: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<Policy>,
+    /// Networks rendered instead of the single mesh, `meshs` then only
+    /// defines their hosts.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub networks: Box<[Network]>,
 }
 
 impl Meshs {
     pub fn new(meshs: impl Into<Box<[Mesh]>>, ipv4_prefix: u8, ipv6_prefix: u8) -> Self {
         Meshs {
+            version: VERSION,
             meshs: meshs.into(),
             ipv4_prefix,
             ipv6_prefix,
+            relay: None,
+            persistent_keepalive: None,
+            policy: None,
+            networks: Box::default(),
         }
     }
+
+    /// Whether the nodes tagged `a` and `b` may peer.
+    pub fn allows(&self, a: &str, b: &str) -> bool {
+        self.policy
+            .as_ref()
+            .is_none_or(|policy| policy.allows(a, b))
+    }
+
+    /// JSON Schema of the config file.
+    pub fn schema() -> serde_json::Value {
+        schemars::schema_for!(Meshs).to_value()
+    }
+}
+
+impl Default for Meshs {
+    fn default() -> Self {
+        Meshs::new([], 0, 0)
+    }
 }
 
 impl Deref for Meshs {
@@ -281,3 +656,5 @@ impl DerefMut for Meshs {
 }
 
 impl_json!(Mesh, Meshs);
+impl_yaml!(Mesh, Meshs);
+impl_toml!(Mesh, Meshs);
@@ -0,0 +1,66 @@
+use serde_json::{Map, Value};
+
+use crate::mesh::VERSION;
+
+const FIELD_VERSION: &str = "version";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Config is not an object")]
+    NotAnObject,
+    #[error("Invalid config version: {0}")]
+    InvalidVersion(Value),
+    #[error("Config version {0} is newer than the supported version {VERSION}")]
+    Newer(u64),
+}
+
+/// A config document upgraded to [`VERSION`].
+#[derive(Debug)]
+pub struct Migrated {
+    pub value: Value,
+    /// The version the document was at, `0` if it had none.
+    pub from: u64,
+    /// What every step changed, empty if the document was already current.
+    pub changes: Box<[Box<str>]>,
+}
+
+type Step = fn(&mut Map<String, Value>, &mut Vec<Box<str>>);
+
+/// `STEPS[n]` upgrades a document from version `n` to `n + 1`.
+const STEPS: [Step; VERSION as usize] = [v0_to_v1];
+
+/// Unversioned documents predate the `version` field and need nothing else.
+fn v0_to_v1(_: &mut Map<String, Value>, changes: &mut Vec<Box<str>>) {
+    changes.push(format!("added `{}` field", FIELD_VERSION).into());
+}
+
+/// Upgrades a config document, in any of the supported formats parsed to a
+/// [`Value`], step by step to the current [`VERSION`].
+pub fn migrate(mut value: Value) -> Result<Migrated, Error> {
+    let object = value.as_object_mut().ok_or(Error::NotAnObject)?;
+    let from = match object.get(FIELD_VERSION) {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| Error::InvalidVersion(version.clone()))?,
+    };
+    if from > VERSION as u64 {
+        return Err(Error::Newer(from));
+    }
+    let mut changes = Vec::new();
+    for (version, step) in STEPS.iter().enumerate().skip(from as usize) {
+        let mut step_changes = Vec::new();
+        step(object, &mut step_changes);
+        object.insert(FIELD_VERSION.into(), (version + 1).into());
+        changes.extend(
+            step_changes
+                .into_iter()
+                .map(|change| format!("{} -> {}: {}", version, version + 1, change).into()),
+        );
+    }
+    Ok(Migrated {
+        value,
+        from,
+        changes: changes.into_boxed_slice(),
+    })
+}
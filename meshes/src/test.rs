@@ -1,7 +1,18 @@
 #![cfg(test)]
 
-use crate::mesh::{
-    EndpointBoxStr, FromJson as _, Ipv4BoxStr, Ipv6BoxStr, Mesh, Meshs, ToJson as _,
+use crate::{
+    conf::{
+        self, Conf, NetworkManager, Networkd, Nix, Readme, Reason, Renderer, RouterOs, Template,
+        Uci, WgQuick, WgSetconf,
+    },
+    export::{Ansible, Exporter as _, Kubernetes},
+    import,
+    mesh::{
+        Action, EndpointBoxStr, FromJson as _, FromToml as _, FromYaml as _, Ipv4BoxStr,
+        Ipv6BoxStr, KeyPair, Member, Mesh, Meshs, Network, Policy, Role, Rule, ToJson as _,
+        ToToml as _, ToYaml as _, VERSION,
+    },
+    migrate,
 };
 
 #[test]
@@ -76,3 +87,1081 @@ fn test_de() {
     mesh.key_pair.prikey = original_values[1].clone();
     Meshs::from_json(Meshs::new([mesh], 33, 129).to_json().unwrap()).unwrap_err();
 }
+
+#[test]
+fn test_yaml_toml() {
+    let meshs = two_node_conf().meshs;
+    let mut meshs = Meshs::new(
+        [
+            meshs[0].clone(),
+            Mesh {
+                key_pair: KeyPair::from_prikey("y3f0fu/krxHKNdt86ElVqBs9jLdvn4AYncjlBKWe/nA=")
+                    .unwrap(),
+                ..meshs[1].clone()
+            },
+        ],
+        meshs.ipv4_prefix,
+        meshs.ipv6_prefix,
+    );
+    assert_eq!(Meshs::from_yaml(meshs.to_yaml().unwrap()).unwrap(), meshs);
+    assert_eq!(Meshs::from_toml(meshs.to_toml().unwrap()).unwrap(), meshs);
+
+    meshs.meshs[0].ipv4 = "invalid-ip".into();
+    Meshs::from_yaml(meshs.to_yaml().unwrap()).unwrap_err();
+    Meshs::from_toml(meshs.to_toml().unwrap()).unwrap_err();
+    meshs.meshs[0].ipv4 = "10.0.0.1".into();
+    meshs.meshs[0].key_pair.pubkey = meshs.meshs[1].key_pair.pubkey.clone();
+    Meshs::from_yaml(meshs.to_yaml().unwrap()).unwrap_err();
+    Meshs::from_toml(meshs.to_toml().unwrap()).unwrap_err();
+    meshs.meshs[0].key_pair.pubkey = "L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q=".into();
+    meshs.ipv4_prefix = 33;
+    Meshs::from_yaml(meshs.to_yaml().unwrap()).unwrap_err();
+    Meshs::from_toml(meshs.to_toml().unwrap()).unwrap_err();
+}
+
+fn two_node_conf() -> Conf {
+    Conf::new(Meshs::new(
+        [
+            Mesh::new(
+                "1",
+                "L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q=",
+                "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+                "10.0.0.1",
+                "fd00::1",
+                Some("test.local.arpa:51820"),
+            ),
+            Mesh::new(
+                "2",
+                "pubkey2",
+                "prikey2",
+                "10.0.0.2",
+                "fd00::2",
+                None::<&str>,
+            ),
+        ],
+        29,
+        126,
+    ))
+}
+
+fn hub_and_spoke_conf() -> Conf {
+    let mut conf = two_node_conf();
+    let mut meshs = conf.meshs.to_vec();
+    meshs.push(Mesh {
+        role: Role::Spoke,
+        ..Mesh::new(
+            "3",
+            "pubkey3",
+            "prikey3",
+            "10.0.0.3",
+            "fd00::3",
+            None::<&str>,
+        )
+    });
+    meshs[1].role = Role::Spoke;
+    conf.meshs.meshs = meshs.into();
+    conf
+}
+
+#[test]
+fn test_hub_and_spoke() {
+    let mut conf = hub_and_spoke_conf();
+    conf.validate().unwrap();
+    assert_eq!(
+        conf.gateway(&conf.meshs[1]).map(|mesh| &*mesh.tag),
+        Some("1")
+    );
+
+    let hub = conf.node(&conf.meshs[0]);
+    assert!(hub.forwarding);
+    assert_eq!(hub.peers.len(), 2);
+    assert_eq!(
+        &*hub.peers[1].allowed_ips,
+        ["10.0.0.3/32".into(), "fd00::3/128".into()]
+    );
+
+    let spoke = conf.node(&conf.meshs[1]);
+    assert!(!spoke.forwarding);
+    assert_eq!(spoke.peers.len(), 1);
+    assert_eq!(&*spoke.peers[0].mesh.tag, "1");
+    assert_eq!(
+        &*spoke.peers[0].allowed_ips,
+        ["10.0.0.0/29".into(), "fd00::/126".into()]
+    );
+
+    let files = conf.create_single(&WgQuick, &conf.meshs[0]).unwrap();
+    assert!(files[0].contents.contains(
+        "\
+Address = fd00::1/126
+PostUp = sysctl -w net.ipv4.ip_forward=1
+PostUp = sysctl -w net.ipv6.conf.all.forwarding=1
+"
+    ));
+
+    conf.meshs[0].role = Role::Spoke;
+    assert!(matches!(conf.validate(), Err(conf::Error::NoHub(_))));
+}
+
+#[test]
+fn test_policy() {
+    let mut conf = hub_and_spoke_conf();
+    conf.meshs[1].role = Role::Hub;
+    conf.meshs[2].role = Role::Hub;
+    conf.meshs[2].endpoint = Some("3.local.arpa:51820".into());
+    conf.meshs.policy = Some(Policy {
+        default: Action::Allow,
+        groups: [
+            ("a".into(), Box::from(["1".into()])),
+            ("b".into(), Box::from(["2".into(), "3".into()])),
+        ]
+        .into(),
+        rules: Box::new([Rule {
+            action: Action::Deny,
+            between: ["@a".into(), "2".into()],
+        }]),
+    });
+    conf.validate().unwrap();
+    let peers = |conf: &Conf, i: usize| -> Vec<Box<str>> {
+        conf.node(&conf.meshs[i])
+            .peers
+            .iter()
+            .map(|peer| peer.mesh.tag.clone())
+            .collect()
+    };
+    assert_eq!(peers(&conf, 0), ["3".into()]);
+    assert_eq!(peers(&conf, 1), ["3".into()]);
+    assert_eq!(peers(&conf, 2), ["1".into(), "2".into()]);
+
+    let policy = conf.meshs.policy.as_mut().unwrap();
+    policy.default = Action::Deny;
+    policy.rules[0] = Rule {
+        action: Action::Allow,
+        between: ["@b".into(), "@b".into()],
+    };
+    assert!(peers(&conf, 0).is_empty());
+    assert_eq!(peers(&conf, 1), ["3".into()]);
+    let policy = conf.meshs.policy.as_ref().unwrap();
+    let toml = toml::to_string(policy).unwrap();
+    assert_eq!(&toml::from_str::<Policy>(&toml).unwrap(), policy);
+
+    // Spoke 2 may only peer with hub 3, which has nothing to forward for it
+    conf.meshs[1].role = Role::Spoke;
+    assert_eq!(
+        conf.gateway(&conf.meshs[1]).map(|mesh| &*mesh.tag),
+        Some("3")
+    );
+    assert_eq!(
+        &*conf.node(&conf.meshs[1]).peers[0].allowed_ips,
+        ["10.0.0.3/32".into(), "fd00::3/128".into()]
+    );
+    assert!(!conf.node(&conf.meshs[2]).forwarding);
+    assert!(!conf.node(&conf.meshs[0]).forwarding);
+
+    conf.meshs.policy.as_mut().unwrap().rules[0].between[1] = "@c".into();
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::UnknownReference { kind: "group", .. })
+    ));
+}
+
+#[test]
+fn test_routes() {
+    let mut conf = hub_and_spoke_conf();
+    conf.meshs[1].routes = Box::new(["192.168.1.0/24".into(), "fd01::/64".into()]);
+    conf.validate().unwrap();
+    assert!(conf.node(&conf.meshs[1]).forwarding);
+    assert_eq!(
+        &*conf.node(&conf.meshs[0]).peers[0].allowed_ips,
+        [
+            "10.0.0.2/32".into(),
+            "fd00::2/128".into(),
+            "192.168.1.0/24".into(),
+            "fd01::/64".into()
+        ]
+    );
+    let spoke = conf.node(&conf.meshs[2]);
+    assert_eq!(
+        &*spoke.peers[0].allowed_ips,
+        [
+            "10.0.0.0/29".into(),
+            "fd00::/126".into(),
+            "192.168.1.0/24".into(),
+            "fd01::/64".into()
+        ]
+    );
+    assert_eq!(
+        spoke.routes().collect::<Vec<_>>(),
+        ["192.168.1.0/24", "fd01::/64"]
+    );
+    let files = conf.create_single(&WgSetconf, &conf.meshs[2]).unwrap();
+    assert!(files[1].contents.contains(
+        "\
+ip route add 192.168.1.0/24 dev wg0
+ip route add fd01::/64 dev wg0
+"
+    ));
+
+    conf.meshs[2].routes = Box::new(["192.168.0.0/16".into()]);
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::OverlappingRoute { tag, .. }) if &*tag == "2"
+    ));
+    conf.meshs[2].routes = Box::new(["10.0.0.0/8".into()]);
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::OverlappingRoute { tag, .. }) if &*tag == "3"
+    ));
+
+    let mut mesh = two_node_conf().meshs[0].clone();
+    mesh.routes = Box::new(["192.168.1.0/24".into()]);
+    assert_eq!(Mesh::from_json(mesh.to_json().unwrap()).unwrap(), mesh);
+    mesh.routes = Box::new(["192.168.1.0".into()]);
+    Mesh::from_json(mesh.to_json().unwrap()).unwrap_err();
+}
+
+#[test]
+fn test_exit_node() {
+    let mut conf = hub_and_spoke_conf();
+    conf.meshs[1].exit_node = Some("1".into());
+    conf.validate().unwrap();
+
+    let client = conf.node(&conf.meshs[1]);
+    assert_eq!(client.exit_node, Some("1"));
+    assert_eq!(
+        &*client.peers[0].allowed_ips,
+        [
+            "10.0.0.0/29".into(),
+            "fd00::/126".into(),
+            "0.0.0.0/0".into(),
+            "::/0".into()
+        ]
+    );
+    assert_eq!(client.routes().count(), 0);
+    let exit = conf.node(&conf.meshs[0]);
+    assert!(exit.masquerade && exit.forwarding);
+    assert!(!conf.node(&conf.meshs[2]).masquerade);
+
+    let files = conf.create_single(&WgQuick, &conf.meshs[0]).unwrap();
+    assert!(files[0].contents.contains(
+        "\
+PostUp = iptables -t nat -A POSTROUTING -s 10.0.0.0/29 ! -o %i -j MASQUERADE
+PostUp = ip6tables -t nat -A POSTROUTING -s fd00::/126 ! -o %i -j MASQUERADE
+PostDown = iptables -t nat -D POSTROUTING -s 10.0.0.0/29 ! -o %i -j MASQUERADE
+PostDown = ip6tables -t nat -D POSTROUTING -s fd00::/126 ! -o %i -j MASQUERADE
+"
+    ));
+    let files = conf
+        .create_single(&Networkd::default(), &conf.meshs[1])
+        .unwrap();
+    assert!(files[0].contents.contains("FirewallMark=51820\n"));
+    assert!(
+        files[1]
+            .contents
+            .contains("[RoutingPolicyRule]\nFirewallMark=51820\n")
+    );
+
+    for (exit_node, reason) in [
+        ("2", "is the node itself"),
+        ("3", "is not a peer"),
+        ("4", "does not exist"),
+    ] {
+        conf.meshs[1].exit_node = Some(exit_node.into());
+        assert!(matches!(
+            conf.validate(),
+            Err(conf::Error::InvalidExitNode { reason: r, .. }) if r == reason
+        ));
+    }
+}
+
+#[test]
+fn test_wg_quick() {
+    let conf = two_node_conf();
+    let files = conf.create_single(&WgQuick, &conf.meshs[1]).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(&*files[0].name, "2.conf");
+    assert!(files[0].private);
+    assert_eq!(
+        &*files[0].contents,
+        "\
+[Interface]
+# PublicKey = pubkey2
+PrivateKey = prikey2
+Address = 10.0.0.2/29
+Address = fd00::2/126
+
+[Peer]
+PublicKey = L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q=
+Endpoint = test.local.arpa:51820
+AllowedIPs = 10.0.0.1/32, fd00::1/128
+PersistentKeepalive = 25
+"
+    );
+    let all = conf.create_all(&WgQuick).unwrap();
+    assert_eq!(all.len(), 2);
+}
+
+#[test]
+fn test_networkd() {
+    let conf = two_node_conf();
+    let files = conf
+        .create_single(&Networkd::default(), &conf.meshs[0])
+        .unwrap();
+    let names: Box<[_]> = files.iter().map(|f| (&*f.name, f.private)).collect();
+    assert_eq!(
+        &*names,
+        [("1.netdev", false), ("1.network", false), ("1.key", true)]
+    );
+    assert!(!files[0].contents.contains("AAAAAAAA"));
+    assert!(
+        files[0]
+            .contents
+            .contains("PrivateKeyFile=/etc/systemd/network/wg0.key\nListenPort=51820\n")
+    );
+    assert!(
+        files[0]
+            .contents
+            .contains("[WireGuardPeer]\nPublicKey=pubkey2\nAllowedIPs=10.0.0.2/32, fd00::2/128\n")
+    );
+    assert!(
+        files[1]
+            .contents
+            .contains("Address=10.0.0.1/29\nAddress=fd00::1/126\n")
+    );
+}
+
+#[test]
+fn test_network_manager() {
+    let conf = two_node_conf();
+    let files = conf.create_single(&NetworkManager, &conf.meshs[1]).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(&*files[0].name, "2.nmconnection");
+    assert!(files[0].private);
+    let contents = &files[0].contents;
+    assert!(!contents.contains("listen-port"));
+    assert!(contents.contains(
+        "[wireguard-peer.L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q=]
+endpoint=test.local.arpa:51820
+allowed-ips=10.0.0.1/32;fd00::1/128;
+"
+    ));
+    assert!(contents.contains("[ipv4]\naddress1=10.0.0.2/29\nmethod=manual\n"));
+    assert!(contents.contains("[ipv6]\naddress1=fd00::2/126\nmethod=manual\n"));
+}
+
+#[test]
+fn test_endpoint_host_port() {
+    let endpoint = EndpointBoxStr::from("[fd00::1]:51820");
+    assert_eq!(endpoint.host(), "fd00::1");
+    assert_eq!(endpoint.port(), "51820");
+    let endpoint = EndpointBoxStr::from("test.local.arpa:51821");
+    assert_eq!(endpoint.host(), "test.local.arpa");
+    assert_eq!(endpoint.port(), "51821");
+}
+
+#[test]
+fn test_uci() {
+    let mut conf = two_node_conf();
+    conf.meshs[0].tag = "it's".into();
+    let files = conf.create_single(&Uci, &conf.meshs[1]).unwrap();
+    let names: Box<[_]> = files.iter().map(|f| &*f.name).collect();
+    assert_eq!(&*names, ["2.uci", "2.batch"]);
+    assert!(files[0].contents.contains(
+        "\
+config wireguard_wg0
+\toption description 'it'\\''s'
+\toption public_key 'L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q='
+\toption endpoint_host 'test.local.arpa'
+\toption endpoint_port '51820'
+"
+    ));
+    assert!(
+        files[1]
+            .contents
+            .contains("add_list network.wg0.addresses='10.0.0.2/29'\n")
+    );
+    assert!(files[1].contents.ends_with("commit network\n"));
+}
+
+#[test]
+fn test_router_os() {
+    let conf = two_node_conf();
+    let files = conf.create_single(&RouterOs, &conf.meshs[1]).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(&*files[0].name, "2.rsc");
+    assert_eq!(
+        &*files[0].contents,
+        r#"/interface wireguard add name="wg0" private-key="prikey2"
+/interface wireguard peers add interface="wg0" comment="1" public-key="L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q=" endpoint-address="test.local.arpa" endpoint-port=51820 persistent-keepalive=25s allowed-address=10.0.0.1/32,fd00::1/128
+/ip address add address=10.0.0.2/29 interface="wg0"
+/ipv6 address add address=fd00::2/126 interface="wg0" advertise=no
+"#
+    );
+}
+
+#[test]
+fn test_wg_setconf() {
+    let conf = two_node_conf();
+    let files = conf.create_single(&WgSetconf, &conf.meshs[0]).unwrap();
+    let names: Box<[_]> = files.iter().map(|f| (&*f.name, f.private)).collect();
+    assert_eq!(&*names, [("1.wg.conf", true), ("1.addr.sh", false)]);
+    assert!(!files[0].contents.contains("Address"));
+    assert!(files[0].contents.contains("ListenPort = 51820\n"));
+    assert!(
+        files[1]
+            .contents
+            .contains("ip address add fd00::1/126 dev wg0\n")
+    );
+}
+
+#[test]
+fn test_readme() {
+    let conf = two_node_conf();
+    let files = conf.create_single(&Readme, &conf.meshs[1]).unwrap();
+    assert_eq!(&*files[0].name, "2.README.md");
+    assert!(!files[0].private);
+    assert!(!files[0].contents.contains("prikey2"));
+    assert!(
+        files[0]
+            .contents
+            .contains("- IPv4: 10.0.0.2/29\n- IPv6: fd00::2/126\n")
+    );
+    assert!(files[0].contents.contains(
+        "| 1 | L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q= | test.local.arpa:51820 | 10.0.0.1/32, fd00::1/128 | 25s |\n"
+    ));
+}
+
+#[test]
+fn test_template() {
+    let conf = two_node_conf();
+    let template = Template::new(
+        "peers.j2",
+        "{{ this.tag }} {{ this.ipv4 }}/{{ ipv4_prefix }} {{ this.role }}
+{% for peer in peers %}{{ peer.tag }} {{ peer.allowed_ips | join(\",\") }} {{ peer.prikey is defined }}
+{% endfor %}",
+        "txt",
+    )
+    .unwrap();
+    let files = conf.create_single(&template, &conf.meshs[1]).unwrap();
+    assert_eq!(&*files[0].name, "2.txt");
+    assert_eq!(
+        &*files[0].contents,
+        "2 10.0.0.2/29 hub\n1 10.0.0.1/32,fd00::1/128 False\n"
+    );
+
+    let template =
+        Template::new("broken.j2", "line one\n{{ this.missing.field }}\n", "txt").unwrap();
+    let err = conf
+        .create_single(&template, &conf.meshs[1])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("(in broken.j2:2)"), "{}", err);
+    Template::new("syntax.j2", "\n\n{% for %}", "txt").unwrap_err();
+}
+
+#[test]
+fn test_ansible() {
+    let conf = two_node_conf();
+    let files = Ansible.export(&conf).unwrap();
+    let names: Box<[_]> = files.iter().map(|f| (&*f.name, f.private)).collect();
+    assert_eq!(
+        &*names,
+        [
+            ("inventory.yml", false),
+            ("host_vars/1.yml", false),
+            ("host_vars/2.yml", false),
+            ("group_vars/wg0/vault.yml", true),
+        ]
+    );
+    assert!(!files[..3].iter().any(|f| f.contents.contains("prikey2")));
+    assert!(files[1].contents.contains("wireguard_listen_port: 51820\n"));
+    assert!(files[3].contents.contains("'2': prikey2\n"));
+}
+
+#[test]
+fn test_nix() {
+    let conf = two_node_conf();
+    let files = conf.create_single(&Nix::default(), &conf.meshs[0]).unwrap();
+    let names: Box<[_]> = files.iter().map(|f| (&*f.name, f.private)).collect();
+    assert_eq!(&*names, [("1.nix", false), ("1.nix.key", true)]);
+    assert!(!files[0].contents.contains("AAAAAAAA"));
+    assert_eq!(
+        &*files[0].contents,
+        r#"{
+  networking.wireguard.interfaces."wg0" = {
+    # publicKey = "L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q="
+    ips = [ "10.0.0.1/29" "fd00::1/126" ];
+    privateKeyFile = "/etc/wireguard/wg0.key";
+    listenPort = 51820;
+    peers = [
+      {
+        # 2
+        publicKey = "pubkey2";
+        allowedIPs = [ "10.0.0.2/32" "fd00::2/128" ];
+      }
+    ];
+  };
+}
+"#
+    );
+}
+
+#[test]
+fn test_kubernetes() {
+    let conf = two_node_conf();
+    let files = Kubernetes {
+        namespace: Some("vpn".into()),
+        name_prefix: "wg-".into(),
+    }
+    .export(&conf)
+    .unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(&*files[1].name, "2.yaml");
+    assert!(files[1].private);
+    assert!(files[1].contents.starts_with(
+        "\
+apiVersion: v1
+kind: Secret
+metadata:
+  name: wg-2
+  namespace: vpn
+  labels:
+    mesh/tag: '2'
+type: Opaque
+stringData:
+  wg0.conf: |
+    [Interface]
+    # PublicKey = pubkey2
+"
+    ));
+
+    let mut conf = conf;
+    for tag in ["Laptop_1", "a.b/c"] {
+        conf.meshs[1].tag = tag.into();
+        assert!(matches!(
+            Kubernetes::default().export(&conf),
+            Err(conf::Error::InvalidKubernetesName { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_import_wg_quick() {
+    let mut conf = two_node_conf();
+    conf.meshs[1].key_pair =
+        KeyPair::from_prikey("y3f0fu/krxHKNdt86ElVqBs9jLdvn4AYncjlBKWe/nA=").unwrap();
+    conf.meshs[1].key_pair.validate().unwrap();
+    let configs: Box<[_]> = conf
+        .meshs
+        .iter()
+        .map(|mesh| {
+            let files = conf.create_single(&WgQuick, mesh).unwrap();
+            (mesh.tag.clone(), files[0].contents.clone())
+        })
+        .collect();
+    let imported = import::wg_quick(configs.iter().map(|(t, c)| (&**t, &**c))).unwrap();
+    assert_eq!(imported.meshs, conf.meshs);
+    assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+
+    let one_sided = configs[1].1.split("[Peer]").next().unwrap();
+    let imported = import::wg_quick([
+        (&*configs[0].0, &*configs[0].1),
+        ("2", &*format!("{}\nPostUp = true\n", one_sided)),
+    ])
+    .unwrap();
+    assert_eq!(imported.meshs.meshs[0].endpoint, None);
+    assert_eq!(
+        &*imported.warnings,
+        [
+            Box::from("2: `PostUp` is not supported and was dropped"),
+            Box::from("1: ListenPort 51820 was dropped, no peer has an Endpoint for it"),
+            Box::from("1 peers with 2, but not the other way around"),
+        ]
+    );
+
+    let imported = import::wg_quick([
+        (
+            &*configs[0].0,
+            &*configs[0]
+                .1
+                .replace("ListenPort = 51820", "ListenPort = 51821"),
+        ),
+        (&*configs[1].0, &*configs[1].1),
+    ])
+    .unwrap();
+    assert_eq!(
+        &*imported.warnings,
+        [Box::from(
+            "1: ListenPort 51821 differs from the port 51820 peers connect to"
+        )]
+    );
+
+    // Routes and exit nodes survive the round trip through AllowedIPs
+    conf.meshs[0].routes = Box::new(["192.168.1.0/24".into()]);
+    conf.meshs[1].exit_node = Some("1".into());
+    let configs: Box<[_]> = conf
+        .meshs
+        .iter()
+        .map(|mesh| {
+            let files = conf.create_single(&WgQuick, mesh).unwrap();
+            (mesh.tag.clone(), files[0].contents.clone())
+        })
+        .collect();
+    let imported = import::wg_quick(configs.iter().map(|(t, c)| (&**t, &**c))).unwrap();
+    assert_eq!(imported.meshs, conf.meshs);
+
+    assert!(matches!(
+        import::wg_quick([(&*configs[0].0, &*configs[0].1), ("copy", &*configs[0].1)]),
+        Err(import::Error::DuplicateKey { ref tag, ref other })
+            if &**tag == "copy" && &**other == "1"
+    ));
+    import::wg_quick([("1", "[Interface]\nPrivateKey = AAAA\n")]).unwrap_err();
+    import::wg_quick([("1", "Address = 10.0.0.1/24\n")]).unwrap_err();
+}
+
+#[test]
+fn test_import_wg_meshconf() {
+    let csv = "\
+Name,Address,Endpoint,AllowedIPs,ListenPort,PersistentKeepalive,FwMark,PrivateKey,DNS,MTU,Table,PreUp,PostUp,PreDown,PostDown,SaveConfig
+1,\"10.0.0.1/24,fd00::1/64\",test.local.arpa,,51820,,,AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=,1.1.1.1,,,,,,,
+2,\"10.0.0.2/24,fd00::2/64\",fd00:1::2,,51821,,,y3f0fu/krxHKNdt86ElVqBs9jLdvn4AYncjlBKWe/nA=,,,,,,,,
+3,\"10.0.0.3,fd00::3\",,,51822,15,,y3f0fu/krxHKNdt86ElVqBs9jLdvn4AYncjlBKWe/nA=,,,,,,,,
+";
+    let imported = import::wg_meshconf(csv.as_bytes()).unwrap();
+    let meshs = &imported.meshs;
+    assert_eq!((meshs.ipv4_prefix, meshs.ipv6_prefix), (24, 64));
+    assert_eq!(
+        meshs.meshs[0].key_pair.pubkey,
+        "L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q=".into()
+    );
+    assert_eq!(
+        meshs.meshs[0].endpoint,
+        Some("test.local.arpa:51820".into())
+    );
+    assert_eq!(meshs.meshs[1].endpoint, Some("[fd00:1::2]:51821".into()));
+    assert_eq!(meshs.meshs[2].endpoint, None);
+    assert_eq!(meshs.meshs[2].persistent_keepalive, Some(15));
+    assert_eq!(
+        &*imported.warnings,
+        [
+            Box::from("3: ListenPort `51822` was dropped, it has no Endpoint"),
+            Box::from("column `DNS` is not supported, its values were dropped for: 1"),
+        ]
+    );
+    Meshs::from_json(meshs.to_json().unwrap()).unwrap();
+
+    let mismatch = "Name,Address,PrivateKey,PublicKey\n1,\"10.0.0.1,fd00::1\",AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=,pubkey\n";
+    import::wg_meshconf(mismatch.as_bytes()).unwrap_err();
+}
+
+#[test]
+fn test_migrate() {
+    let meshs = Meshs::new([two_node_conf().meshs[0].clone()], 24, 64);
+    let mut value = serde_json::to_value(&meshs).unwrap();
+    value.as_object_mut().unwrap().remove("version");
+    Meshs::from_json(value.to_string()).unwrap_err();
+
+    let migrated = migrate::migrate(value).unwrap();
+    assert_eq!(migrated.from, 0);
+    assert_eq!(
+        &*migrated.changes,
+        [Box::from("0 -> 1: added `version` field")]
+    );
+    assert_eq!(
+        serde_json::from_value::<Meshs>(migrated.value).unwrap(),
+        meshs
+    );
+
+    let migrated = migrate::migrate(serde_json::to_value(&meshs).unwrap()).unwrap();
+    assert_eq!(migrated.from, VERSION as u64);
+    assert!(migrated.changes.is_empty());
+
+    let mut value = serde_json::to_value(&meshs).unwrap();
+    value["version"] = (VERSION + 1).into();
+    migrate::migrate(value.clone()).unwrap_err();
+    Meshs::from_json(value.to_string()).unwrap_err();
+}
+
+#[test]
+fn test_schema() {
+    let validator = jsonschema::options()
+        .should_validate_formats(true)
+        .build(&Meshs::schema())
+        .unwrap();
+    let meshs = Meshs::new([two_node_conf().meshs[0].clone()], 24, 64);
+    let valid = serde_json::to_value(&meshs).unwrap();
+    assert!(validator.is_valid(&valid));
+
+    let cases: &[(&str, serde_json::Value)] = &[
+        ("/version", 0.into()),
+        ("/version", 2.into()),
+        ("/ipv4_prefix", 32.into()),
+        ("/ipv4_prefix", 33.into()),
+        ("/ipv6_prefix", 128.into()),
+        ("/ipv6_prefix", 129.into()),
+        ("/meshs/0/ipv4", "10.0.0.256".into()),
+        ("/meshs/0/ipv4", "fd00::1".into()),
+        ("/meshs/0/ipv6", "fd00::g".into()),
+        ("/meshs/0/ipv6", "10.0.0.1".into()),
+        ("/meshs/0/endpoint", serde_json::Value::Null),
+        ("/meshs/0/endpoint", "host".into()),
+        ("/meshs/0/endpoint", "host:1".into()),
+        ("/meshs/0/endpoint", "fd00::1:51820".into()),
+        ("/meshs/0/endpoint", "[fd00::1]:51820".into()),
+        ("/meshs/0/endpoint", "[fd00::1]".into()),
+        ("/meshs/0/endpoint", "[fd00::1:51820".into()),
+        ("/meshs/0/endpoint", "]host[:1".into()),
+        ("/meshs/0/pubkey", "".into()),
+        (
+            "/meshs/0/pubkey",
+            "L+V9o0fNYkMVKNqsX7spBzD/9oSvxM/C7ZCZX1jLO3Q".into(),
+        ),
+        (
+            "/meshs/0/prikey",
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAB=".into(),
+        ),
+        (
+            "/meshs/0/prikey",
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".into(),
+        ),
+    ];
+    for (pointer, value) in cases {
+        let mut doc = valid.clone();
+        *doc.pointer_mut(pointer).unwrap() = value.clone();
+        assert_eq!(
+            validator.is_valid(&doc),
+            serde_json::from_value::<Meshs>(doc).is_ok(),
+            "{} = {}",
+            pointer,
+            value
+        );
+    }
+    for field in ["tag", "pubkey", "ipv4", "endpoint"] {
+        let mut doc = valid.clone();
+        doc["meshs"][0].as_object_mut().unwrap().remove(field);
+        assert_eq!(
+            validator.is_valid(&doc),
+            serde_json::from_value::<Meshs>(doc).is_ok(),
+            "without {}",
+            field
+        );
+    }
+}
+
+#[test]
+fn test_relay() {
+    let mut conf = hub_and_spoke_conf();
+    conf.meshs[1].role = Role::Hub;
+    conf.meshs[2].role = Role::Hub;
+    conf.meshs[2].routes = Box::new(["192.168.3.0/24".into()]);
+    conf.validate().unwrap();
+    assert_eq!(conf.relay().map(|mesh| &*mesh.tag), Some("1"));
+
+    // Neither 2 nor 3 has an endpoint, so they go through 1
+    let node = conf.node(&conf.meshs[1]);
+    assert_eq!(node.peers.len(), 1);
+    assert_eq!(
+        &*node.peers[0].allowed_ips,
+        [
+            "10.0.0.1/32".into(),
+            "fd00::1/128".into(),
+            "10.0.0.3/32".into(),
+            "fd00::3/128".into(),
+            "192.168.3.0/24".into(),
+        ]
+    );
+    assert!(!node.forwarding);
+    assert!(conf.node(&conf.meshs[0]).forwarding);
+    let routes = conf.routes(&conf.meshs[1]);
+    assert_eq!(routes[4].reason, Reason::Relay(&conf.meshs[2]));
+
+    conf.meshs.relay = Some("2".into());
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::InvalidRelay {
+            reason: "has no endpoint",
+            ..
+        })
+    ));
+    conf.meshs.relay = None;
+    conf.meshs.policy = Some(Policy {
+        rules: Box::new([Rule {
+            action: Action::Deny,
+            between: ["1".into(), "3".into()],
+        }]),
+        ..Policy::default()
+    });
+    assert!(matches!(conf.validate(), Err(conf::Error::Unreachable(_))));
+}
+
+#[test]
+fn test_keepalive() {
+    let mut conf = two_node_conf();
+    let keepalive = |conf: &Conf, i: usize| conf.node(&conf.meshs[i]).peers[0].persistent_keepalive;
+    assert_eq!(keepalive(&conf, 0), None);
+    assert_eq!(keepalive(&conf, 1), Some(25));
+
+    conf.meshs.persistent_keepalive = Some(15);
+    assert_eq!(keepalive(&conf, 1), Some(15));
+    conf.meshs[1].persistent_keepalive = Some(0);
+    assert_eq!(keepalive(&conf, 1), None);
+    // Node 2 has no endpoint to send keepalives to
+    conf.meshs[0].persistent_keepalive = Some(10);
+    assert_eq!(keepalive(&conf, 0), None);
+
+    conf.meshs[1].endpoint = Some("2.local.arpa:51820".into());
+    assert_eq!(keepalive(&conf, 0), Some(10));
+    assert_eq!(keepalive(&conf, 1), None);
+    let files = conf
+        .create_single(&Networkd::default(), &conf.meshs[0])
+        .unwrap();
+    assert!(files[0].contents.contains("PersistentKeepalive=10\n"));
+}
+
+#[test]
+fn test_location_endpoints() {
+    let mut conf = hub_and_spoke_conf();
+    conf.meshs[1].role = Role::Hub;
+    conf.meshs[2].role = Role::Hub;
+    conf.meshs[1].location = Some("home".into());
+    conf.meshs[2].endpoints = [("home".into(), "192.168.1.3:51821".into())].into();
+    conf.meshs[0].endpoints = [("home".into(), "192.168.1.1:51820".into())].into();
+    conf.validate().unwrap();
+
+    // 2 reaches 3 on the LAN instead of through the relay
+    let node = conf.node(&conf.meshs[1]);
+    let endpoints: Box<[_]> = node
+        .peers
+        .iter()
+        .map(|peer| (&*peer.mesh.tag, peer.endpoint.map(|e| &**e)))
+        .collect();
+    assert_eq!(
+        &*endpoints,
+        [
+            ("1", Some("192.168.1.1:51820")),
+            ("3", Some("192.168.1.3:51821"))
+        ]
+    );
+    assert_eq!(
+        conf.node(&conf.meshs[2]).peers[0].endpoint.map(|e| &**e),
+        Some("test.local.arpa:51820")
+    );
+    let files = conf.create_single(&WgQuick, &conf.meshs[2]).unwrap();
+    assert!(files[0].contents.contains("ListenPort = 51821\n"));
+
+    conf.meshs[0].endpoints = [("home".into(), "192.168.1.1:51821".into())].into();
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::MismatchedPort { .. })
+    ));
+}
+
+#[test]
+fn test_networks() {
+    let mut conf = two_node_conf();
+    let member = |tag: &str, ipv4: Option<&str>, ipv6: Option<&str>| Member {
+        tag: tag.into(),
+        ipv4: ipv4.map(Into::into),
+        ipv6: ipv6.map(Into::into),
+        routes: None,
+    };
+    conf.meshs.networks = Box::new([
+        Network {
+            name: "prod".into(),
+            listen_port: 51820,
+            ipv4_prefix: 29,
+            ipv6_prefix: 126,
+            members: Box::new([member("1", None, None), member("2", None, None)]),
+        },
+        Network {
+            name: "mgmt".into(),
+            listen_port: 51821,
+            ipv4_prefix: 24,
+            ipv6_prefix: 64,
+            members: Box::new([
+                member("1", Some("10.1.0.1"), Some("fd01::1")),
+                member("2", Some("10.1.0.2"), Some("fd01::2")),
+            ]),
+        },
+    ]);
+    let all = conf.create_all(&WgQuick).unwrap();
+    let names: Box<[_]> = all["1"].iter().map(|file| &*file.name).collect();
+    assert_eq!(&*names, ["prod/1.conf", "mgmt/1.conf"]);
+    assert!(
+        all["1"][1]
+            .contents
+            .contains("ListenPort = 51821\nAddress = 10.1.0.1/24\n")
+    );
+    assert!(
+        all["2"][1]
+            .contents
+            .contains("Endpoint = test.local.arpa:51821\nAllowedIPs = 10.1.0.1/32, fd01::1/128\n")
+    );
+
+    conf.meshs.networks[1].members[1].tag = "3".into();
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::InvalidMember {
+            reason: "does not exist",
+            ..
+        })
+    ));
+    conf.meshs.networks[1].members[1].tag = "2".into();
+    conf.meshs.networks[1].listen_port = 51820;
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::NetworkClash {
+            what: "listen port",
+            ..
+        })
+    ));
+    conf.meshs.networks[1].listen_port = 51821;
+
+    conf.meshs.networks[1].members = Box::new([member("1", None, None), member("2", None, None)]);
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::SharedAddress { ref tag, ref network, .. })
+            if &**tag == "1" && &**network == "mgmt"
+    ));
+    conf.meshs.networks[1].members = Box::new([
+        member("1", Some("10.1.0.1"), Some("fd01::1")),
+        member("2", Some("10.1.0.1"), Some("fd01::2")),
+    ]);
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::InvalidMember {
+            reason: "reuses the address of another member",
+            ..
+        })
+    ));
+    conf.meshs.networks[1].members[1] = member("2", Some("10.2.0.2"), Some("fd01::2"));
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::InvalidMember {
+            reason: "is outside of the network's subnet",
+            ..
+        })
+    ));
+
+    conf.meshs.networks[1].members = Box::new([
+        member("1", Some("10.0.0.9"), Some("fd01::1")),
+        member("2", Some("10.0.0.10"), Some("fd01::2")),
+    ]);
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::OverlappingNetwork { ref subnet, ref other_subnet, .. })
+            if &**subnet == "10.0.0.0/24" && &**other_subnet == "10.0.0.0/29"
+    ));
+    conf.meshs.networks[1].members = Box::new([
+        member("1", Some("10.1.0.1"), Some("fd01::1")),
+        member("2", Some("10.1.0.2"), Some("fd01::2")),
+    ]);
+    conf.validate().unwrap();
+
+    conf.meshs.meshs[1].routes = Box::new(["192.168.1.0/24".into()]);
+    assert!(matches!(
+        conf.validate(),
+        Err(conf::Error::OverlappingRoute { ref tag, ref other, .. })
+            if &**tag == "2" && &**other == "192.168.1.0/24 of \"2\" in \"mgmt\""
+    ));
+    conf.meshs.networks[1].members[1].routes = Some(Box::default());
+    conf.validate().unwrap();
+    let all = conf.create_all(&WgQuick).unwrap();
+    assert!(all["1"][0].contents.contains("192.168.1.0/24"));
+    assert!(!all["1"][1].contents.contains("192.168.1.0/24"));
+}
+
+#[test]
+fn test_file_names_unique() {
+    let conf = two_node_conf();
+    let renderers: [&dyn Renderer; 8] = [
+        &WgQuick,
+        &WgSetconf,
+        &Networkd::default(),
+        &NetworkManager,
+        &Uci,
+        &RouterOs,
+        &Nix::default(),
+        &Readme,
+    ];
+    let mut names = Vec::new();
+    for renderer in renderers {
+        for file in conf.create_single(renderer, &conf.meshs[0]).unwrap() {
+            assert!(!names.contains(&file.name), "{} written twice", file.name);
+            names.push(file.name);
+        }
+    }
+}
+
+#[test]
+fn test_policy_between_spokes() {
+    let mut conf = hub_and_spoke_conf();
+    conf.meshs[2].routes = Box::new(["192.168.3.0/24".into()]);
+    conf.meshs.policy = Some(Policy {
+        rules: Box::new([Rule {
+            action: Action::Deny,
+            between: ["2".into(), "3".into()],
+        }]),
+        ..Policy::default()
+    });
+    conf.validate().unwrap();
+
+    // Neither spoke routes the other through the hub, which forwards nothing
+    for (i, other) in [(1, "3"), (2, "2")] {
+        let node = conf.node(&conf.meshs[i]);
+        assert_eq!(
+            &node.peers[0].allowed_ips[..2],
+            ["10.0.0.1/32".into(), "fd00::1/128".into()]
+        );
+        assert!(conf.routes(&conf.meshs[i]).iter().all(|route| {
+            !route.destination.starts_with(&*format!("10.0.0.{}", other))
+                && !route.destination.starts_with("192.168.3.")
+        }));
+    }
+    assert!(!conf.node(&conf.meshs[0]).forwarding);
+
+    conf.meshs.policy = None;
+    assert_eq!(
+        &*conf.node(&conf.meshs[1]).peers[0].allowed_ips,
+        [
+            "10.0.0.0/29".into(),
+            "fd00::/126".into(),
+            "192.168.3.0/24".into()
+        ]
+    );
+}
+
+#[test]
+fn test_unreachable_gateway() {
+    let mut conf = hub_and_spoke_conf();
+    let mut meshs = conf.meshs.to_vec();
+    meshs[0].endpoint = None;
+    meshs.push(Mesh::new(
+        "4",
+        "pubkey4",
+        "prikey4",
+        "10.0.0.4",
+        "fd00::4",
+        Some("4.local.arpa:51820"),
+    ));
+    conf.meshs.meshs = meshs.into();
+    conf.meshs.ipv6_prefix = 125;
+    conf.validate().unwrap();
+
+    // Hub 1 cannot be reached, so hub 4 is the gateway of both spokes
+    assert_eq!(
+        conf.gateway(&conf.meshs[1]).map(|mesh| &*mesh.tag),
+        Some("4")
+    );
+    let node = conf.node(&conf.meshs[1]);
+    assert_eq!(node.peers.len(), 1);
+    assert_eq!(
+        &node.peers[0].allowed_ips[..2],
+        ["10.0.0.0/29".into(), "fd00::/125".into()]
+    );
+    assert!(!conf.node(&conf.meshs[0]).forwarding);
+    assert!(conf.node(&conf.meshs[3]).forwarding);
+
+    conf.meshs[3].endpoint = None;
+    conf.meshs.relay = None;
+    assert!(matches!(conf.validate(), Err(conf::Error::NoHub(_))));
+}